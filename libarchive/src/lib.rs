@@ -4,19 +4,20 @@
 //! Basic safe Rust bindings for libarchive.
 //!
 //! This library is minimal, implementing only what's needed for pine. Currently it supports
-//! reading archives (in any format/filter combination the libarchive supports) and iterating
-//! through their header/metadata entries.
+//! reading archives (in any format/filter combination the libarchive supports), iterating
+//! through their header/metadata entries, and reading the data of each entry.
 
 #![warn(unsafe_op_in_unsafe_fn)]
 
 use std::borrow::Borrow;
 use std::ffi::{CStr, OsStr};
 use std::fmt;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::os::raw::{c_char, c_int, c_void};
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[allow(non_camel_case_types)]
 #[allow(non_snake_case)]
@@ -106,6 +107,41 @@ impl ArchiveEntry {
         unsafe { raw_cstring_to_pathbuf(ffi::archive_entry_symlink(self.ptr)) }
     }
 
+    /// Target path if this entry is a hard link to an earlier entry in the archive. Hardlink
+    /// entries usually have the file type of a regular file, so check this first.
+    pub fn hardlink_path(&self) -> Option<PathBuf> {
+        unsafe { raw_cstring_to_pathbuf(ffi::archive_entry_hardlink(self.ptr)) }
+    }
+
+    /// Size of the entry's data in bytes, or None if the archive doesn't record it.
+    pub fn size(&self) -> Option<u64> {
+        unsafe {
+            if ffi::archive_entry_size_is_set(self.ptr) != 0 {
+                u64::try_from(ffi::archive_entry_size(self.ptr)).ok()
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Modification time of the entry, or None if the archive doesn't record it.
+    pub fn mtime(&self) -> Option<SystemTime> {
+        unsafe {
+            if ffi::archive_entry_mtime_is_set(self.ptr) == 0 {
+                return None;
+            }
+            let secs = ffi::archive_entry_mtime(self.ptr);
+            let nsecs = ffi::archive_entry_mtime_nsec(self.ptr);
+            let offset = Duration::from_secs(secs.unsigned_abs());
+            let time = if secs >= 0 {
+                UNIX_EPOCH.checked_add(offset)
+            } else {
+                UNIX_EPOCH.checked_sub(offset)
+            };
+            time?.checked_add(Duration::from_nanos(u64::try_from(nsecs).ok()?))
+        }
+    }
+
     pub fn filetype(&self) -> u32 {
         unsafe { ffi::archive_entry_filetype(self.ptr) }
     }
@@ -130,6 +166,104 @@ impl ArchiveEntry {
         self.filetype() == ffi::AE_IFLNK
     }
 
+    pub fn is_fifo(&self) -> bool {
+        self.filetype() == ffi::AE_IFIFO
+    }
+
+    pub fn is_socket(&self) -> bool {
+        self.filetype() == ffi::AE_IFSOCK
+    }
+
+    pub fn is_block_device(&self) -> bool {
+        self.filetype() == ffi::AE_IFBLK
+    }
+
+    pub fn is_char_device(&self) -> bool {
+        self.filetype() == ffi::AE_IFCHR
+    }
+
+    /// Major and minor device numbers of a block or character device entry.
+    pub fn rdev(&self) -> (u32, u32) {
+        unsafe {
+            (
+                ffi::archive_entry_rdevmajor(self.ptr) as u32,
+                ffi::archive_entry_rdevminor(self.ptr) as u32,
+            )
+        }
+    }
+
+    /// Sparse map of the entry as a list of (offset, length) data regions. Anything outside of
+    /// these regions is a hole. Empty if the entry isn't sparse.
+    pub fn sparse_map(&self) -> Vec<(u64, u64)> {
+        let mut regions = Vec::new();
+        unsafe {
+            // like xattrs, the iterator state is internal to the entry
+            if ffi::archive_entry_sparse_reset(self.ptr) == 0 {
+                return regions;
+            }
+            let mut offset: ffi::la_int64_t = 0;
+            let mut length: ffi::la_int64_t = 0;
+            while ffi::archive_entry_sparse_next(self.ptr, &mut offset, &mut length)
+                == ffi::ARCHIVE_OK
+            {
+                regions.push((offset as u64, length as u64));
+            }
+        }
+        regions
+    }
+
+    /// Whether the entry's data or metadata is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        unsafe { ffi::archive_entry_is_encrypted(self.ptr) != 0 }
+    }
+
+    /// Extended attributes of the entry as (name, value) pairs, in the order they're stored in the
+    /// archive.
+    pub fn xattrs(&self) -> Vec<(String, Vec<u8>)> {
+        let mut xattrs = Vec::new();
+        unsafe {
+            // the xattr iterator is internal to the entry, but isn't otherwise observable, so this
+            // is fine to do with a shared reference.
+            ffi::archive_entry_xattr_reset(self.ptr);
+            let mut name: *const c_char = std::ptr::null();
+            let mut value: *const c_void = std::ptr::null();
+            let mut size: usize = 0;
+            while ffi::archive_entry_xattr_next(self.ptr, &mut name, &mut value, &mut size)
+                == ffi::ARCHIVE_OK
+            {
+                if name.is_null() {
+                    continue;
+                }
+                let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+                let value = if value.is_null() {
+                    Vec::new()
+                } else {
+                    std::slice::from_raw_parts(value.cast::<u8>(), size).to_vec()
+                };
+                xattrs.push((name, value));
+            }
+        }
+        xattrs
+    }
+
+    /// ACL of the entry in libarchive's text format (one entry per line, POSIX.1e or NFSv4 style
+    /// depending on the ACL type), or None if the entry has no ACL.
+    pub fn acl_text(&self) -> Option<String> {
+        unsafe {
+            let types = ffi::ARCHIVE_ENTRY_ACL_TYPE_POSIX1E | ffi::ARCHIVE_ENTRY_ACL_TYPE_NFS4;
+            if ffi::archive_entry_acl_count(self.ptr, types) == 0 {
+                return None;
+            }
+            let text = ffi::archive_entry_acl_to_text(self.ptr, std::ptr::null_mut(), 0);
+            if text.is_null() {
+                return None;
+            }
+            let ret = CStr::from_ptr(text).to_string_lossy().into_owned();
+            libc::free(text.cast());
+            Some(ret)
+        }
+    }
+
     fn as_ptr(&mut self) -> *mut ffi::archive_entry {
         self.ptr
     }
@@ -175,7 +309,8 @@ impl ArchiveError {
         self
     }
 
-    fn context(mut self, prefix: impl ToString) -> Self {
+    /// Add a prefix to the error message, e.g. to describe what was being done when it failed.
+    pub fn context(mut self, prefix: impl ToString) -> Self {
         self.prefix = Some(prefix.to_string());
         self
     }
@@ -343,11 +478,76 @@ impl<R: Read> ArchiveReader<R> {
         }
     }
 
+    /// Read data for the entry most recently returned by `read_next_header` into `buf`. Returns
+    /// the number of bytes read, or 0 at the end of the entry's data.
+    pub fn read_data(&mut self, buf: &mut [u8]) -> Result<usize, ArchiveError> {
+        let ret = unsafe { ffi::archive_read_data(self.ptr, buf.as_mut_ptr().cast(), buf.len()) };
+        match usize::try_from(ret) {
+            Ok(count) => Ok(count),
+            Err(_) => Err(self.last_error()),
+        }
+    }
+
+    /// Get an `io::Read` implementation for the data of the entry most recently returned by
+    /// `read_next_header`.
+    pub fn data(&mut self) -> EntryData<'_, R> {
+        EntryData { archive: self }
+    }
+
+    /// Human-readable name of the archive format, e.g. "POSIX pax interchange format". Only
+    /// meaningful after reading the first header.
+    pub fn format_name(&mut self) -> Option<String> {
+        unsafe {
+            let name = ffi::archive_format_name(self.ptr);
+            (!name.is_null()).then(|| CStr::from_ptr(name).to_string_lossy().into_owned())
+        }
+    }
+
+    /// Names of the filters (usually compression) applied to the archive, starting from the one
+    /// closest to the archive data, i.e. in the same order as file extensions like `.tar.xz.gz`.
+    /// The final "none" filter that reads the raw input isn't included.
+    pub fn filter_names(&mut self) -> Vec<String> {
+        let mut names = Vec::new();
+        unsafe {
+            for i in 0..ffi::archive_filter_count(self.ptr) {
+                let name = ffi::archive_filter_name(self.ptr, i);
+                if !name.is_null() {
+                    let name = CStr::from_ptr(name).to_string_lossy();
+                    if name != "none" {
+                        names.push(name.into_owned());
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    /// Whether the archive contains encrypted entries, or None if the format doesn't support
+    /// encryption or it can't be determined yet (e.g. before reading the first header).
+    pub fn has_encrypted_entries(&mut self) -> Option<bool> {
+        match unsafe { ffi::archive_read_has_encrypted_entries(self.ptr) } {
+            0 => Some(false),
+            n if n > 0 => Some(true),
+            _ => None,
+        }
+    }
+
     pub fn last_error(&mut self) -> ArchiveError {
         unsafe { ArchiveError::from_archive(self.ptr) }
     }
 }
 
+/// Reader for the data of the current entry in an ArchiveReader, see [`ArchiveReader::data`].
+pub struct EntryData<'a, R: Read> {
+    archive: &'a mut ArchiveReader<R>,
+}
+
+impl<R: Read> Read for EntryData<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.archive.read_data(buf).map_err(io::Error::other)
+    }
+}
+
 impl<R: Read + Seek> ArchiveReader<R> {
     /// seek callback for an archive reader. See general safety notes on read_callback.
     ///
@@ -0,0 +1,78 @@
+// Copyright (c) 2021 Allen Wild <allenwild93@gmail.com>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fmt::Write as _;
+use std::io::{self, Read};
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+/// Hash algorithms available for `--checksum`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    Sha256,
+    Md5,
+    Blake3,
+}
+
+impl Checksum {
+    /// Parse an algorithm name as given on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(Self::Sha256),
+            "md5" => Some(Self::Md5),
+            "blake3" => Some(Self::Blake3),
+            _ => None,
+        }
+    }
+
+    /// Read all data from `reader` and return its hash as a lowercase hex string.
+    pub fn hash_reader<R: Read>(&self, mut reader: R) -> io::Result<String> {
+        match self {
+            Self::Sha256 => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut reader, &mut hasher)?;
+                Ok(hex(&hasher.finalize()))
+            }
+            Self::Md5 => {
+                let mut hasher = Md5::new();
+                io::copy(&mut reader, &mut hasher)?;
+                Ok(hex(&hasher.finalize()))
+            }
+            Self::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                io::copy(&mut reader, &mut hasher)?;
+                Ok(hex(hasher.finalize().as_bytes()))
+            }
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Checksum;
+
+    #[test]
+    fn test_hashes() {
+        let data = b"hello world";
+        assert_eq!(
+            Checksum::Sha256.hash_reader(&data[..]).unwrap(),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert_eq!(
+            Checksum::Md5.hash_reader(&data[..]).unwrap(),
+            "5eb63bbbe01eeed093cb22bb8f5acdc3"
+        );
+        assert_eq!(
+            Checksum::Blake3.hash_reader(&data[..]).unwrap(),
+            "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"
+        );
+    }
+}
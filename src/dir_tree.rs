@@ -1,15 +1,26 @@
 // Copyright (c) 2021 Allen Wild <allenwild93@gmail.com>
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::borrow::Cow;
+use std::cmp::Reverse;
 use std::collections::btree_map::{BTreeMap, Entry as BTreeEntry};
+use std::ffi::OsString;
+use std::fmt;
 use std::io::{self, Write};
+use std::ops::AddAssign;
 use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
 
 use libarchive::ArchiveError;
 use lscolors::{Indicator, LsColors};
-use termcolor::WriteColor;
+use termcolor::{Color, ColorSpec, NoColor, WriteColor};
+use unicode_width::UnicodeWidthStr;
 
+use crate::git::GitStatus;
+use crate::icons::Icons;
+use crate::quote::QuotingStyle;
 use crate::util::*;
+use crate::xattr::Xattr;
 
 pub type DirTreeResult = Result<DirTree, DirTreeError>;
 
@@ -17,10 +28,53 @@ pub type DirTreeResult = Result<DirTree, DirTreeError>;
 pub enum Entry {
     File,
     ExecFile,
-    Symlink(PathBuf),
+    /// A symbolic link with its target path, and whether we know that target exists.
+    Symlink {
+        target: PathBuf,
+        state: LinkState,
+    },
+    /// A hard link to another entry, holding the path of the link target within the tree.
+    Hardlink(PathBuf),
+    Fifo,
+    Socket,
+    BlockDevice(DeviceNumber),
+    CharDevice(DeviceNumber),
     Directory(DirTree),
 }
 
+/// Whether the target of a symlink exists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// Not checked, or impossible to tell (e.g. an absolute symlink inside an archive)
+    #[default]
+    Unknown,
+    /// The target exists
+    Valid,
+    /// The target doesn't exist, or the link can't be resolved
+    Broken,
+}
+
+/// Major and minor numbers of a block or character device node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceNumber {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DeviceNumber {
+    /// Split a combined `dev_t` value (e.g. `st_rdev`) into major and minor numbers.
+    pub fn from_dev(dev: u64) -> Self {
+        // SAFETY: major and minor only do arithmetic on their argument
+        unsafe { Self { major: libc::major(dev), minor: libc::minor(dev) } }
+    }
+}
+
+impl fmt::Display for DeviceNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.major, self.minor)
+    }
+}
+
 impl Default for Entry {
     fn default() -> Self {
         Self::empty_dir()
@@ -32,21 +86,46 @@ impl Entry {
         Self::Directory(Default::default())
     }
 
+    /// Create a symlink entry whose target hasn't been checked.
+    pub fn symlink(target: impl Into<PathBuf>) -> Self {
+        Self::Symlink { target: target.into(), state: LinkState::Unknown }
+    }
+
     /// Write a colored version of `name` to the specified Writer. Files are colored based on file
-    /// extensions, directories as such, and symlinks also write the target, formatted as a file
-    /// name based on extension.
-    fn write_styled_name<W>(&self, w: &mut W, name: &Path, color: &LsColors) -> io::Result<()>
+    /// extensions, directories as such, and symlinks and hardlinks also write the target, formatted
+    /// as a file name based on extension. Broken symlinks and their targets use the orphan and
+    /// missing file styles. Device nodes are followed by their major:minor numbers. If enabled,
+    /// the name is followed by a classify suffix. `text` is the name as displayed, already quoted,
+    /// and `name` only picks its style. If `target` is given, it's shown instead of the quoted
+    /// link target. If `url` is given, the name is wrapped in an OSC 8 hyperlink to it.
+    fn write_styled_name<W>(
+        &self,
+        w: &mut W,
+        name: &Path,
+        text: &str,
+        target: Option<&str>,
+        url: Option<&str>,
+        opts: &PrintOptions,
+    ) -> io::Result<()>
     where
         W: Write + WriteColor,
     {
+        let color = &opts.color;
         let style = if w.supports_color() {
             match self {
                 // we can't create a std::fs::Metadata, but passing None makes lscolors assume
                 // a regular file to be styled by file extension
-                Entry::File => color.style_for_path_with_metadata(name, None),
-                // for executable files, symlinks and dirs, get a style based on indicator type
+                Entry::File | Entry::Hardlink(_) => color.style_for_path_with_metadata(name, None),
+                // for everything else, get a style based on indicator type
                 Entry::ExecFile => color.style_for_indicator(Indicator::ExecutableFile),
-                Entry::Symlink(_) => color.style_for_indicator(Indicator::SymbolicLink),
+                Entry::Symlink { state: LinkState::Broken, .. } => {
+                    color.style_for_indicator(Indicator::OrphanedSymbolicLink)
+                }
+                Entry::Symlink { .. } => color.style_for_indicator(Indicator::SymbolicLink),
+                Entry::Fifo => color.style_for_indicator(Indicator::FIFO),
+                Entry::Socket => color.style_for_indicator(Indicator::Socket),
+                Entry::BlockDevice(_) => color.style_for_indicator(Indicator::BlockDevice),
+                Entry::CharDevice(_) => color.style_for_indicator(Indicator::CharacterDevice),
                 Entry::Directory(_) => color.style_for_indicator(Indicator::Directory),
             }
         } else {
@@ -54,71 +133,601 @@ impl Entry {
             None
         };
 
-        match style.map(ToColorSpec::to_color_spec) {
-            Some(cs) => {
-                w.set_color(&cs)?;
-                write!(w, "{}", name.display())?;
-                w.reset()?;
-            }
-            None => write!(w, "{}", name.display())?,
+        if let Some(url) = url {
+            write!(w, "\x1b]8;;{}\x1b\\", url)?;
+        }
+        write_with_style(w, text, style)?;
+        if url.is_some() {
+            write!(w, "\x1b]8;;\x1b\\")?;
+        }
+        if opts.classify {
+            write!(w, "{}", self.classify_suffix())?;
         }
 
-        // optionally print symlink target
-        if let Entry::Symlink(target) = self {
-            // cheat slightly by recursively calling this function
-            write!(w, " -> ")?;
-            Entry::File.write_styled_name(w, target, color)?;
+        // optionally print symlink or hardlink target
+        let target_text = self
+            .link_target()
+            .map(|t| target.map_or_else(|| opts.quoting.quote(t.as_os_str()), Cow::Borrowed))
+            .unwrap_or_default();
+        match self {
+            Entry::Symlink { state: LinkState::Broken, .. } => {
+                write!(w, " -> ")?;
+                let style = if w.supports_color() {
+                    color.style_for_indicator(Indicator::MissingFile)
+                } else {
+                    None
+                };
+                write_with_style(w, &target_text, style)?;
+            }
+            Entry::Symlink { target: link_target, .. } => {
+                // cheat slightly by recursively calling this function
+                write!(w, " -> ")?;
+                Entry::File.write_styled_name(w, link_target, &target_text, None, None, opts)?;
+            }
+            Entry::Hardlink(link_target) => {
+                write!(w, " => ")?;
+                Entry::File.write_styled_name(w, link_target, &target_text, None, None, opts)?;
+            }
+            Entry::BlockDevice(dev) | Entry::CharDevice(dev) => write!(w, " [{}]", dev)?,
+            _ => (),
         }
 
         Ok(())
     }
 
+    /// Target path of a symlink or hardlink.
+    fn link_target(&self) -> Option<&Path> {
+        match self {
+            Entry::Symlink { target, .. } | Entry::Hardlink(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Display width of everything written after the name by `write_styled_name`, other than the
+    /// link target itself.
+    fn extra_width(&self, opts: &PrintOptions) -> usize {
+        let suffix = if opts.classify { self.classify_suffix().len() } else { 0 };
+        suffix
+            + match self {
+                Entry::Symlink { .. } | Entry::Hardlink(_) => " -> ".len(),
+                Entry::BlockDevice(dev) | Entry::CharDevice(dev) => format!(" [{}]", dev).len(),
+                _ => 0,
+            }
+    }
+
+    /// Type indicator character appended to names by `--classify`, the same as `ls -F`.
+    fn classify_suffix(&self) -> &'static str {
+        match self {
+            Entry::ExecFile => "*",
+            Entry::Symlink { .. } => "@",
+            Entry::Fifo => "|",
+            Entry::Socket => "=",
+            Entry::Directory(_) => "/",
+            Entry::File | Entry::Hardlink(_) | Entry::BlockDevice(_) | Entry::CharDevice(_) => "",
+        }
+    }
+}
+
+/// Write `text` in the given style, or unstyled if `style` is None.
+fn write_with_style<W>(w: &mut W, text: &str, style: Option<&lscolors::Style>) -> io::Result<()>
+where
+    W: Write + WriteColor,
+{
+    match style.map(ToColorSpec::to_color_spec) {
+        Some(cs) => {
+            w.set_color(&cs)?;
+            write!(w, "{}", text)?;
+            w.reset()
+        }
+        None => write!(w, "{}", text),
+    }
+}
+
+/// Shorten a name and link target so that they fit in `avail` columns. The target is shortened
+/// first, down to MIN_TARGET_WIDTH, so that both stay recognizable.
+fn fit_to_width<'a>(
+    name: Cow<'a, str>,
+    target: Option<Cow<'a, str>>,
+    avail: usize,
+) -> (Cow<'a, str>, Option<Cow<'a, str>>) {
+    const MIN_TARGET_WIDTH: usize = 8;
+
+    let name_width = name.width();
+    let target_width = target.as_ref().map_or(0, |t| t.width());
+    if name_width + target_width <= avail {
+        return (name, target);
+    }
+
+    let excess = name_width + target_width - avail;
+    let new_target_width =
+        target_width.saturating_sub(excess).max(target_width.min(MIN_TARGET_WIDTH));
+    let new_name_width = avail.saturating_sub(new_target_width).max(1);
+    let truncate = |s: Cow<'a, str>, width: usize| match truncate_to_width(&s, width) {
+        Cow::Borrowed(_) => s,
+        Cow::Owned(short) => Cow::Owned(short),
+    };
+    (truncate(name, new_name_width), target.map(|t| truncate(t, new_target_width)))
+}
+
+/// Extra metadata about an entry, filled in when the input source provides it.
+#[derive(Debug, Default, Clone)]
+pub struct EntryMeta {
+    /// size of the file's contents in bytes
+    pub size: Option<u64>,
+    /// inode number, for files on disk
+    pub inode: Option<u64>,
+    /// ID of the device containing the file, for files on disk
+    pub device: Option<u64>,
+    /// last modification time
+    pub mtime: Option<SystemTime>,
+    /// hex checksum of the file's contents, if requested
+    pub checksum: Option<String>,
+    /// short name of the file's type detected from its contents, if requested
+    pub magic: Option<&'static str>,
+    /// size of the file's data as stored in the archive, for formats that compress each entry
+    /// separately
+    pub compressed_size: Option<u64>,
+    /// number of bytes of actual data in a sparse file, not counting holes
+    pub allocated_size: Option<u64>,
+    /// the entry's comment in the archive, if requested
+    pub comment: Option<String>,
+    /// absolute path of a file on disk
+    pub abs_path: Option<PathBuf>,
+    /// status in the git work tree, rolled up from the children of directories
+    pub git: Option<GitStatus>,
+    /// whether the entry's data or metadata is encrypted in the archive
+    pub encrypted: bool,
+    /// extended attributes, other than ACLs, if requested
+    pub xattrs: Vec<Xattr>,
+    /// access control list as getfacl-style text lines, if requested
+    pub acl: Vec<String>,
+}
+
+/// A single item in a DirTree, the entry itself plus any metadata we know about it.
+#[derive(Debug, Default)]
+pub struct Node {
+    pub entry: Entry,
+    pub meta: EntryMeta,
+}
+
+impl Node {
+    pub fn new(entry: Entry, meta: EntryMeta) -> Self {
+        Self { entry, meta }
+    }
+
+    fn empty_dir() -> Self {
+        Entry::empty_dir().into()
+    }
+
+    /// Write this node and its children as lines of the tree. `path` is the full path of this
+    /// entry from the root of the tree.
     fn write_to<W>(
         &self,
         w: &mut W,
-        name: &Path,
+        path: &Path,
         prefix: &str,
         root_entry: bool,
         last_in_dir: bool,
-        color: &LsColors,
+        opts: &PrintOptions,
     ) -> io::Result<()>
     where
         W: Write + WriteColor,
     {
-        write!(
-            w,
-            "{prefix}{leader}",
-            prefix = prefix,
-            leader = if root_entry {
+        let guides = &opts.guides;
+        let leader = if root_entry {
+            ""
+        } else if last_in_dir {
+            guides.last
+        } else {
+            guides.branch
+        };
+        write!(w, "{}{}", prefix, leader)?;
+        let name =
+            if opts.full_path { path } else { path.file_name().map(Path::new).unwrap_or(path) };
+        let text = opts.quoting.quote(name.as_os_str());
+        let target = self.entry.link_target().map(|t| opts.quoting.quote(t.as_os_str()));
+        let (text, target) = match opts.width {
+            Some(width) => {
+                let used = prefix.width() + leader.width() + self.decoration_width(opts);
+                fit_to_width(text, target, width.saturating_sub(used))
+            }
+            None => (text, target),
+        };
+        self.write_columns(w, opts)?;
+        if opts.git {
+            self.write_git_marker(w)?;
+        }
+        if let Some(icons) = &opts.icons {
+            write!(w, "{} ", icons.icon_for(&self.entry, path))?;
+        }
+        let url = match (&opts.hyperlink_host, &self.meta.abs_path) {
+            (Some(host), Some(abs_path)) => Some(file_url(host, abs_path)),
+            _ => None,
+        };
+        self.entry.write_styled_name(w, name, &text, target.as_deref(), url.as_deref(), opts)?;
+        self.write_annotations(w, opts)?;
+        writeln!(w)?;
+
+        let new_prefix = format!(
+            "{}{}",
+            prefix,
+            if root_entry {
                 ""
             } else if last_in_dir {
-                "└── "
+                guides.blank
             } else {
-                "├── "
-            },
-        )?;
-        self.write_styled_name(w, name, color)?;
-        writeln!(w)?;
+                guides.vertical
+            }
+        );
+        self.write_details(w, &new_prefix, opts)?;
 
-        if let Entry::Directory(dir) = self {
-            let new_prefix = format!(
-                "{}{}",
-                prefix,
-                if root_entry {
-                    ""
-                } else if last_in_dir {
-                    "    "
-                } else {
-                    "│   "
-                }
-            );
-            let mut it = dir.0.iter().peekable();
-            while let Some((name, entry)) = it.next() {
-                entry.write_to(w, name, &new_prefix, false, it.peek().is_none(), color)?;
+        if let Entry::Directory(dir) = &self.entry {
+            let mut it = dir.sorted_entries(opts).into_iter().peekable();
+            while let Some((name, node)) = it.next() {
+                let child_path = path.join(name);
+                node.write_to(w, &child_path, &new_prefix, false, it.peek().is_none(), opts)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Display width of everything on this entry's line other than the tree guides, name, and link
+    /// target.
+    fn decoration_width(&self, opts: &PrintOptions) -> usize {
+        let mut buf = NoColor::new(Vec::new());
+        // writing to a Vec can't fail
+        let _ = self.write_columns(&mut buf, opts);
+        let _ = self.write_annotations(&mut buf, opts);
+        // git markers and icons are both one character and a space
+        let git = if opts.git { 2 } else { 0 };
+        let icon = if opts.icons.is_some() { 2 } else { 0 };
+        String::from_utf8_lossy(buf.get_ref()).width() + git + icon + self.entry.extra_width(opts)
+    }
+
+    /// Write the lines of extra information shown below an entry, like its ACL and extended
+    /// attributes. `prefix` is the prefix used for this entry's children, so that the tree lines
+    /// continue past the details.
+    fn write_details<W: Write>(
+        &self,
+        w: &mut W,
+        prefix: &str,
+        opts: &PrintOptions,
+    ) -> io::Result<()> {
+        let bar = match &self.entry {
+            Entry::Directory(dir) if !dir.0.is_empty() => opts.guides.vertical.trim_end(),
+            _ => " ",
+        };
+        // ACLs from archives contain user and group names, so escape them like comments
+        for line in &self.meta.acl {
+            writeln!(w, "{}{}  {}", prefix, bar, opts.quoting.escape_text(line))?;
+        }
+        for xattr in &self.meta.xattrs {
+            writeln!(w, "{}{}  {}", prefix, bar, xattr.display(opts.quoting))?;
+        }
+        Ok(())
+    }
+
+    /// Write extra information about the entry that's shown after its name.
+    fn write_annotations<W>(&self, w: &mut W, opts: &PrintOptions) -> io::Result<()>
+    where
+        W: Write + WriteColor,
+    {
+        if self.meta.encrypted {
+            write!(w, " ")?;
+            w.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
+            write!(w, "[encrypted]")?;
+            w.reset()?;
+        }
+        if let (true, Entry::Directory(dir)) = (opts.dir_counts, &self.entry) {
+            let stats = dir.stats();
+            write!(
+                w,
+                " [{}; {}, {}]",
+                plural(dir.0.len() as u64, "entry", "entries"),
+                plural(stats.dirs, "dir", "dirs"),
+                plural(stats.non_dirs(), "file", "files"),
+            )?;
+        }
+        if let Some(magic) = self.meta.magic {
+            write!(w, " [{}]", magic)?;
+        }
+        if let Some(allocated) = self.meta.allocated_size {
+            write!(
+                w,
+                " [sparse, {} of {} allocated]",
+                human_size(allocated),
+                human_size(self.meta.size.unwrap_or(0))
+            )?;
+        }
+        let compression = match &self.entry {
+            Entry::Directory(dir) => dir.compression_totals(),
+            _ => self.meta.compressed_size.map(|c| (c, self.meta.size.unwrap_or(0))),
+        };
+        if let Some((compressed, size)) = compression {
+            write!(w, "  [{} / {}", human_size(compressed), human_size(size))?;
+            if size != 0 {
+                write!(w, ", {:.0}%", compressed as f64 * 100.0 / size as f64)?;
             }
+            write!(w, "]")?;
+        }
+        if let Some(checksum) = &self.meta.checksum {
+            write!(w, "  {}", checksum)?;
+        }
+        if let Some(comment) = &self.meta.comment {
+            write!(w, "  # {}", opts.quoting.escape_text(comment))?;
         }
         Ok(())
     }
+
+    /// Write the git status marker and a space, or just spaces if the entry is unchanged.
+    fn write_git_marker<W>(&self, w: &mut W) -> io::Result<()>
+    where
+        W: Write + WriteColor,
+    {
+        match self.meta.git {
+            Some(status) => {
+                w.set_color(ColorSpec::new().set_fg(Some(status.color())).set_intense(true))?;
+                write!(w, "{}", status.marker())?;
+                w.reset()?;
+                write!(w, " ")
+            }
+            None => write!(w, "  "),
+        }
+    }
+
+    /// Write the bracketed metadata columns shown before the name, if any are enabled, e.g.
+    /// `[ 1234567  2049]  `. Missing values are shown as a dash.
+    fn write_columns<W: Write>(&self, w: &mut W, opts: &PrintOptions) -> io::Result<()> {
+        fn column(value: Option<u64>, width: usize) -> String {
+            match value {
+                Some(v) => format!("{:>width$}", v, width = width),
+                None => format!("{:>width$}", "-", width = width),
+            }
+        }
+
+        let mut columns = Vec::new();
+        if opts.inodes {
+            columns.push(column(self.meta.inode, 8));
+        }
+        if opts.device {
+            columns.push(column(self.meta.device, 4));
+        }
+        if !columns.is_empty() {
+            write!(w, "[{}]  ", columns.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Entry> for Node {
+    fn from(entry: Entry) -> Self {
+        Self { entry, meta: EntryMeta::default() }
+    }
+}
+
+/// Order of the entries within each directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// byte-wise order of names
+    #[default]
+    Name,
+    /// names with numbers compared by their value, so that `lib-1.9` comes before `lib-1.10`
+    Version,
+    /// largest first
+    Size,
+    /// newest first
+    Mtime,
+    /// by extension, and then by name
+    Extension,
+}
+
+impl SortOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(Self::Name),
+            "version" => Some(Self::Version),
+            "size" => Some(Self::Size),
+            "mtime" => Some(Self::Mtime),
+            "extension" => Some(Self::Extension),
+            _ => None,
+        }
+    }
+}
+
+/// Where directories are listed relative to the other entries in the same directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DirPlacement {
+    /// in sort order along with everything else
+    #[default]
+    Mixed,
+    First,
+    Last,
+}
+
+/// The strings used to draw the lines of the tree. Each one should have the same display width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guides {
+    /// leader for an entry that has more entries after it in the same directory
+    pub branch: &'static str,
+    /// leader for the last entry in a directory
+    pub last: &'static str,
+    /// prefix below an entry that has more entries after it, continuing the line down
+    pub vertical: &'static str,
+    /// prefix below the last entry in a directory
+    pub blank: &'static str,
+}
+
+impl Guides {
+    pub const ASCII: Self = Self { branch: "|-- ", last: "`-- ", vertical: "|   ", blank: "    " };
+    pub const UTF8: Self =
+        Self { branch: "├── ", last: "└── ", vertical: "│   ", blank: "    " };
+    pub const ROUNDED: Self =
+        Self { branch: "├── ", last: "╰── ", vertical: "│   ", blank: "    " };
+    pub const HEAVY: Self =
+        Self { branch: "┣━━ ", last: "┗━━ ", vertical: "┃   ", blank: "    " };
+    /// plain indentation, without lines
+    pub const INDENT: Self = Self { branch: "    ", last: "    ", vertical: "    ", blank: "    " };
+    /// everything flush left, like `tree -i`
+    pub const NO_INDENT: Self = Self { branch: "", last: "", vertical: "", blank: "" };
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ascii" => Some(Self::ASCII),
+            "utf8" => Some(Self::UTF8),
+            "rounded" => Some(Self::ROUNDED),
+            "heavy" => Some(Self::HEAVY),
+            _ => None,
+        }
+    }
+
+    /// Pick UTF-8 box drawing characters if the locale's character set is UTF-8, and ASCII
+    /// otherwise. Like the C library, the first of $LC_ALL, $LC_CTYPE, and $LANG that's set
+    /// decides.
+    pub fn from_locale() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|val| !val.is_empty())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if locale.contains("utf-8") || locale.contains("utf8") {
+            Self::UTF8
+        } else {
+            Self::ASCII
+        }
+    }
+}
+
+impl Default for Guides {
+    fn default() -> Self {
+        Self::UTF8
+    }
+}
+
+/// Options that control how a DirTree is printed.
+#[derive(Debug, Default)]
+pub struct PrintOptions {
+    /// LS_COLORS styles used for entry names
+    pub color: LsColors,
+    /// print a summary line with the number of directories and files after the tree
+    pub report: bool,
+    /// include the total size of all files in the summary line
+    pub report_size: bool,
+    /// append a type indicator (one of `*/@|=`) to names
+    pub classify: bool,
+    /// print each entry's full path from the root of the tree rather than only its name
+    pub full_path: bool,
+    /// show the inode number of each entry
+    pub inodes: bool,
+    /// show the device ID of each entry
+    pub device: bool,
+    /// show the number of direct and recursive children of each directory
+    pub dir_counts: bool,
+    /// show the format and compression filters of archives next to the root
+    pub archive_format: bool,
+    /// show a git status marker before each name
+    pub git: bool,
+    /// truncate names and link targets so that lines fit in this many columns
+    pub width: Option<usize>,
+    /// make the names of files on disk into hyperlinks, with this hostname in the URL
+    pub hyperlink_host: Option<String>,
+    /// show an icon before each name
+    pub icons: Option<Icons>,
+    /// characters used to draw the tree
+    pub guides: Guides,
+    /// how names and link targets are quoted
+    pub quoting: QuotingStyle,
+    /// order of the entries within each directory
+    pub sort: SortOrder,
+    /// reverse the sort order
+    pub reverse: bool,
+    /// group directories before or after other entries, after sorting
+    pub dir_placement: DirPlacement,
+}
+
+/// Counts of each kind of entry in a DirTree, used for the summary report.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TreeStats {
+    pub dirs: u64,
+    pub files: u64,
+    pub exec_files: u64,
+    pub symlinks: u64,
+    pub hardlinks: u64,
+    pub fifos: u64,
+    pub sockets: u64,
+    pub block_devices: u64,
+    pub char_devices: u64,
+    /// number of files and executables whose data is encrypted
+    pub encrypted: u64,
+    /// total size in bytes of all files with a known size
+    pub size: u64,
+}
+
+impl AddAssign for TreeStats {
+    fn add_assign(&mut self, other: Self) {
+        self.dirs += other.dirs;
+        self.files += other.files;
+        self.exec_files += other.exec_files;
+        self.symlinks += other.symlinks;
+        self.hardlinks += other.hardlinks;
+        self.fifos += other.fifos;
+        self.sockets += other.sockets;
+        self.block_devices += other.block_devices;
+        self.char_devices += other.char_devices;
+        self.encrypted += other.encrypted;
+        self.size += other.size;
+    }
+}
+
+impl TreeStats {
+    /// Total number of entries of any kind other than directories.
+    pub fn non_dirs(&self) -> u64 {
+        self.files
+            + self.exec_files
+            + self.symlinks
+            + self.hardlinks
+            + self.fifos
+            + self.sockets
+            + self.block_devices
+            + self.char_devices
+    }
+
+    /// Whether there are encrypted files and every file is encrypted.
+    pub fn all_encrypted(&self) -> bool {
+        self.encrypted != 0 && self.encrypted == self.files + self.exec_files
+    }
+
+    /// Write the summary report line, e.g. "2 directories, 5 files, 1 symlink". Directories and
+    /// files are always listed (for similarity with tree's output), other kinds only if non-zero.
+    pub fn write_report<W: Write>(&self, w: &mut W, with_size: bool) -> io::Result<()> {
+        write!(
+            w,
+            "{}, {}",
+            plural(self.dirs, "directory", "directories"),
+            plural(self.files, "file", "files")
+        )?;
+        for (count, singular, plural_name) in [
+            (self.exec_files, "executable", "executables"),
+            (self.symlinks, "symlink", "symlinks"),
+            (self.hardlinks, "hardlink", "hardlinks"),
+            (self.fifos, "fifo", "fifos"),
+            (self.sockets, "socket", "sockets"),
+            (self.block_devices, "block device", "block devices"),
+            (self.char_devices, "character device", "character devices"),
+        ] {
+            if count != 0 {
+                write!(w, ", {}", plural(count, singular, plural_name))?;
+            }
+        }
+        if with_size {
+            write!(w, " ({})", human_size(self.size))?;
+        }
+        writeln!(w)
+    }
+}
+
+fn plural(count: u64, singular: &str, plural: &str) -> String {
+    format!("{} {}", count, if count == 1 { singular } else { plural })
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -139,31 +748,34 @@ pub enum DirTreeError {
 }
 
 #[derive(Debug, Default)]
-pub struct DirTree(BTreeMap<PathBuf, Entry>);
+pub struct DirTree(BTreeMap<PathBuf, Node>);
 
 impl DirTree {
     /// Insert a new entry into the DirTree. Returns FileExists if the final path component already
     /// exists, or NotADirectory if an intermediate path component exists that's not a directory.
     #[inline]
-    pub fn insert(&mut self, path: impl AsRef<Path>, entry: Entry) -> Result<(), DirTreeError> {
-        self._insert(path.as_ref(), entry, false)
+    pub fn insert(
+        &mut self,
+        path: impl AsRef<Path>,
+        node: impl Into<Node>,
+    ) -> Result<(), DirTreeError> {
+        self._insert(path.as_ref(), node.into(), false)
     }
 
     /// Insert a new entry or replace an existing entry in the DirTree. If the full path exists,
     /// the existing entry is replaced. If an intermediate path component exists that's not
     /// a directory, it's replaced with a directory. In both cases, the old entry is discarded.
     #[inline]
-    pub fn replace(&mut self, path: impl AsRef<Path>, entry: Entry) -> Result<(), DirTreeError> {
-        self._insert(path.as_ref(), entry, true)
-    }
-
-    fn _insert(
+    pub fn replace(
         &mut self,
-        path: &Path,
-        new_entry: Entry,
-        replace: bool,
+        path: impl AsRef<Path>,
+        node: impl Into<Node>,
     ) -> Result<(), DirTreeError> {
-        match path.components().last() {
+        self._insert(path.as_ref(), node.into(), true)
+    }
+
+    fn _insert(&mut self, path: &Path, new_node: Node, replace: bool) -> Result<(), DirTreeError> {
+        match path.components().next_back() {
             // Normally Rust ignores trailing slashes and '.'s like "/foo/bar/.", but when there's
             // no normal component in front (e.g. "./", "/.", or "/"), things get dicey. Just skip
             // those entries entirely because there's no good way to insert them into the tree.
@@ -191,25 +803,23 @@ impl DirTree {
                     }
                 };
 
-                let entry = cur.0.entry(PathBuf::from(comp)).or_insert_with(Entry::empty_dir);
-                if let Entry::Directory(child_dir) = entry {
-                    // Intermediate path component is a directory as expected
-                    cur = child_dir;
-                } else if replace {
-                    // Intermediate path component isn't a directory, clobber it, discarding
-                    // whatever used to be there.
-                    let _ = std::mem::replace(entry, Entry::empty_dir());
-                    // Slightly ugly match because we need a mut ref to the inside of the entry
-                    // after it's created and we can't borrow directly from an enum variant without
-                    // matching.
-                    cur = match entry {
-                        Entry::Directory(child_dir) => child_dir,
-                        _ => unreachable!(),
-                    };
-                } else {
-                    // Intermediate path component isn't a directory, return an error.
-                    return Err(DirTreeError::NotADirectory(dir.iter().take(i + 1).collect()));
+                let node = cur.0.entry(PathBuf::from(comp)).or_insert_with(Node::empty_dir);
+                if !matches!(node.entry, Entry::Directory(_)) {
+                    if replace {
+                        // Intermediate path component isn't a directory, clobber it, discarding
+                        // whatever used to be there.
+                        *node = Node::empty_dir();
+                    } else {
+                        // Intermediate path component isn't a directory, return an error.
+                        return Err(DirTreeError::NotADirectory(dir.iter().take(i + 1).collect()));
+                    }
                 }
+                // Slightly ugly match because we need a mut ref to the inside of the entry and we
+                // can't borrow directly from an enum variant without matching.
+                cur = match &mut node.entry {
+                    Entry::Directory(child_dir) => child_dir,
+                    _ => unreachable!(),
+                };
             }
         }
 
@@ -222,62 +832,310 @@ impl DirTree {
         })?;
 
         if replace {
-            cur.0.insert(new_name, new_entry);
+            cur.0.insert(new_name, new_node);
         } else if let BTreeEntry::Vacant(slot) = cur.0.entry(new_name) {
-            slot.insert(new_entry);
+            slot.insert(new_node);
         } else {
             return Err(DirTreeError::FileExists(path.into()));
         }
         Ok(())
     }
 
-    fn write_to<W>(&self, w: &mut W, root: Option<&str>, color: &LsColors) -> io::Result<()>
+    /// Get the node at `path`, without following any symlinks. Returns None if the path doesn't
+    /// exist in the tree, or contains components other than normal names and '.'.
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&Node> {
+        let mut cur = self;
+        let mut node: Option<&Node> = None;
+        for comp in path.as_ref().components() {
+            let name = match comp {
+                Component::CurDir => continue,
+                Component::Normal(name) => name,
+                _ => return None,
+            };
+            // descend into the node found by the previous component, which must be a directory
+            if let Some(prev) = node {
+                match &prev.entry {
+                    Entry::Directory(dir) => cur = dir,
+                    _ => return None,
+                }
+            }
+            node = Some(cur.0.get(Path::new(name))?);
+        }
+        node
+    }
+
+    /// Mutable version of [`DirTree::get`]
+    pub fn get_mut(&mut self, path: impl AsRef<Path>) -> Option<&mut Node> {
+        let path = path.as_ref();
+        let parent = match dirname(path) {
+            Some(dir) => match self.get_mut(dir)? {
+                Node { entry: Entry::Directory(dir), .. } => dir,
+                _ => return None,
+            },
+            None => self,
+        };
+        match path.components().next_back()? {
+            Component::Normal(name) => parent.0.get_mut(Path::new(name)),
+            _ => None,
+        }
+    }
+
+    /// Check whether the target of each symlink in the tree exists within the tree, and update
+    /// their LinkState accordingly. Relative targets are resolved from the link's parent
+    /// directory, following other symlinks along the way. Absolute targets, and relative ones that
+    /// point outside of the tree, are left as Unknown since they may refer to things outside of
+    /// whatever we've loaded.
+    pub fn resolve_symlinks(&mut self) {
+        let mut states = Vec::new();
+        self.collect_link_states(self, &mut Vec::new(), &mut states);
+        for (path, new_state) in states {
+            if let Some(Node { entry: Entry::Symlink { state, .. }, .. }) = self.get_mut(&path) {
+                *state = new_state;
+            }
+        }
+    }
+
+    /// Recursive helper for resolve_symlinks. `dir` is the list of path components leading from
+    /// `root` to `self`.
+    fn collect_link_states(
+        &self,
+        root: &DirTree,
+        dir: &mut Vec<OsString>,
+        states: &mut Vec<(PathBuf, LinkState)>,
+    ) {
+        for (name, node) in self.0.iter() {
+            match &node.entry {
+                Entry::Symlink { target, .. } => {
+                    let state = match root.resolve_link(dir, target, 0) {
+                        Ok(_) => LinkState::Valid,
+                        Err(state) => state,
+                    };
+                    let mut path: PathBuf = dir.iter().collect();
+                    path.push(name);
+                    states.push((path, state));
+                }
+                Entry::Directory(subdir) => {
+                    dir.push(name.as_os_str().to_owned());
+                    subdir.collect_link_states(root, dir, states);
+                    dir.pop();
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Resolve the symlink `target` relative to the directory `dir` (a list of path components
+    /// from the root of this tree). On success, return the fully resolved path components of the
+    /// target, otherwise the LinkState describing why it failed.
+    fn resolve_link(
+        &self,
+        dir: &[OsString],
+        target: &Path,
+        depth: u32,
+    ) -> Result<Vec<OsString>, LinkState> {
+        // same limit as Linux's MAXSYMLINKS, beyond which we get ELOOP
+        const MAX_LINK_DEPTH: u32 = 40;
+        if depth > MAX_LINK_DEPTH {
+            return Err(LinkState::Broken);
+        }
+
+        let mut resolved = dir.to_vec();
+        let mut comps = target.components().peekable();
+        while let Some(comp) = comps.next() {
+            let name = match comp {
+                Component::CurDir => continue,
+                Component::ParentDir => {
+                    // walking up past the root means the target is outside of the tree
+                    resolved.pop().ok_or(LinkState::Unknown)?;
+                    continue;
+                }
+                Component::Normal(name) => name,
+                Component::Prefix(_) | Component::RootDir => return Err(LinkState::Unknown),
+            };
+
+            let cur_path: PathBuf = resolved.iter().collect();
+            let node = self.get(cur_path.join(name)).ok_or(LinkState::Broken)?;
+            match &node.entry {
+                Entry::Symlink { target, .. } => {
+                    resolved = self.resolve_link(&resolved, target, depth + 1)?;
+                }
+                Entry::Directory(_) => resolved.push(name.to_owned()),
+                // anything else can only be the final path component
+                _ if comps.peek().is_some() => return Err(LinkState::Broken),
+                _ => resolved.push(name.to_owned()),
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Call `f` on every node in the tree, recursively.
+    pub fn for_each_mut(&mut self, f: &mut impl FnMut(&mut Node)) {
+        for node in self.0.values_mut() {
+            f(node);
+            if let Entry::Directory(dir) = &mut node.entry {
+                dir.for_each_mut(f);
+            }
+        }
+    }
+
+    /// Count the directories, files, etc. in this tree, recursively.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats::default();
+        for node in self.0.values() {
+            match &node.entry {
+                Entry::File => stats.files += 1,
+                Entry::ExecFile => stats.exec_files += 1,
+                Entry::Symlink { .. } => stats.symlinks += 1,
+                Entry::Hardlink(_) => stats.hardlinks += 1,
+                Entry::Fifo => stats.fifos += 1,
+                Entry::Socket => stats.sockets += 1,
+                Entry::BlockDevice(_) => stats.block_devices += 1,
+                Entry::CharDevice(_) => stats.char_devices += 1,
+                Entry::Directory(dir) => {
+                    stats.dirs += 1;
+                    stats += dir.stats();
+                }
+            }
+            // hardlinks share data with their target, so they don't count toward the total size
+            if matches!(node.entry, Entry::File | Entry::ExecFile) {
+                stats.size += node.meta.size.unwrap_or(0);
+                if node.meta.encrypted {
+                    stats.encrypted += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    /// Total (compressed, uncompressed) size of all files in this directory and its
+    /// subdirectories that have a known compressed size. None if there aren't any.
+    pub fn compression_totals(&self) -> Option<(u64, u64)> {
+        let mut totals = None;
+        for node in self.0.values() {
+            let sizes = match &node.entry {
+                Entry::Directory(dir) => dir.compression_totals(),
+                Entry::File | Entry::ExecFile => {
+                    node.meta.compressed_size.map(|c| (c, node.meta.size.unwrap_or(0)))
+                }
+                _ => None,
+            };
+            if let Some((compressed, size)) = sizes {
+                let (total_compressed, total_size) = totals.get_or_insert((0, 0));
+                *total_compressed += compressed;
+                *total_size += size;
+            }
+        }
+        totals
+    }
+
+    /// The entries of this directory, in the order they're printed.
+    fn sorted_entries(&self, opts: &PrintOptions) -> Vec<(&PathBuf, &Node)> {
+        let mut entries: Vec<_> = self.0.iter().collect();
+        // the map is already sorted by name, and the sort is stable, so ties stay in name order
+        match opts.sort {
+            SortOrder::Name => (),
+            SortOrder::Version => entries.sort_by(|a, b| version_cmp(a.0, b.0)),
+            SortOrder::Size => entries.sort_by_key(|e| Reverse(e.1.meta.size)),
+            SortOrder::Mtime => entries.sort_by_key(|e| Reverse(e.1.meta.mtime)),
+            SortOrder::Extension => entries.sort_by(|a, b| a.0.extension().cmp(&b.0.extension())),
+        }
+        if opts.reverse {
+            entries.reverse();
+        }
+        let is_dir = |e: &(&PathBuf, &Node)| matches!(e.1.entry, Entry::Directory(_));
+        match opts.dir_placement {
+            DirPlacement::Mixed => (),
+            DirPlacement::First => entries.sort_by_key(|e| !is_dir(e)),
+            DirPlacement::Last => entries.sort_by_key(is_dir),
+        }
+        entries
+    }
+
+    /// Write the tree, with an optional root line followed by any notes about the tree. Without
+    /// a root, the top-level entries are written as roots themselves.
+    fn write_to<W>(
+        &self,
+        w: &mut W,
+        root: Option<&str>,
+        notes: &[String],
+        opts: &PrintOptions,
+    ) -> io::Result<()>
     where
         W: Write + WriteColor,
     {
         if let Some(ref root) = root {
             writeln!(w, "{}", root)?;
         }
+        // notes can include archive comments, so escape them like entry comments
+        for note in notes {
+            writeln!(w, "[{}]", opts.quoting.escape_text(note))?;
+        }
 
-        let mut it = self.0.iter().peekable();
-        while let Some((name, entry)) = it.next() {
-            entry.write_to(w, name, "", root.is_none(), it.peek().is_none(), color)?;
+        let mut it = self.sorted_entries(opts).into_iter().peekable();
+        while let Some((name, node)) = it.next() {
+            node.write_to(w, name, "", root.is_none(), it.peek().is_none(), opts)?;
         }
         Ok(())
     }
 
-    pub fn print_with_root<W>(&self, w: &mut W, root: &str, color: &LsColors) -> io::Result<()>
+    pub fn print_with_root<W>(
+        &self,
+        w: &mut W,
+        root: &str,
+        notes: &[String],
+        opts: &PrintOptions,
+    ) -> io::Result<()>
     where
         W: Write + WriteColor,
     {
-        self.write_to(w, Some(root), color)
+        self.write_to(w, Some(root), notes, opts)
     }
 
-    pub fn print<W>(&self, w: &mut W, color: &LsColors) -> io::Result<()>
+    pub fn print<W>(&self, w: &mut W, opts: &PrintOptions) -> io::Result<()>
     where
         W: Write + WriteColor,
     {
-        self.write_to(w, None, color)
+        self.write_to(w, None, &[], opts)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
+
     use lscolors::LsColors;
     use termcolor::NoColor;
 
-    use super::{DirTree, DirTreeResult, Entry};
+    use super::{
+        DeviceNumber, DirPlacement, DirTree, DirTreeResult, Entry, EntryMeta, Guides, LinkState,
+        Node, PrintOptions, SortOrder, TreeStats,
+    };
+    use crate::quote::QuotingStyle;
+    use crate::xattr::Xattr;
+
+    /// Print a tree without colors and return the output.
+    fn render(dt: &DirTree, root: Option<&str>, opts: &PrintOptions) -> String {
+        let mut v = NoColor::new(Vec::<u8>::new());
+        dt.write_to(&mut v, root, &[], opts).unwrap();
+        String::from_utf8(v.into_inner()).unwrap()
+    }
 
     fn make_tree() -> DirTreeResult {
         let mut dt = DirTree::default();
         dt.insert("./foo", Entry::empty_dir())?;
         dt.insert("foo/bar", Entry::File)?;
-        dt.insert("foo/baz", Entry::Symlink("symlink target".into()))?;
+        dt.insert("foo/baz", Entry::symlink("symlink target"))?;
         dt.insert("foo/subdir", Entry::empty_dir())?;
         dt.insert("foo/subdir2/subdir3/subdir_file", Entry::File)?;
         dt.insert("another_dir/some_file", Entry::File)?;
+        dt.insert("dev/null", Entry::CharDevice(DeviceNumber { major: 1, minor: 3 }))?;
+        dt.insert("dev/initctl", Entry::Fifo)?;
         dt.insert("zed/asdf/ghjk", Entry::File)?;
-        dt.insert("zed/b", Entry::File)?;
+        dt.insert(
+            "zed/b",
+            Node::new(Entry::File, EntryMeta { size: Some(1024), ..Default::default() }),
+        )?;
+        dt.insert("zed/c", Entry::Hardlink("zed/b".into()))?;
         Ok(dt)
     }
 
@@ -287,6 +1145,9 @@ mod tests {
 root
 ├── another_dir
 │   └── some_file
+├── dev
+│   ├── initctl
+│   └── null [1:3]
 ├── foo
 │   ├── bar
 │   ├── baz -> symlink target
@@ -297,14 +1158,390 @@ root
 └── zed
     ├── asdf
     │   └── ghjk
-    └── b
+    ├── b
+    └── c => zed/b
 ";
         let dt = make_tree().unwrap();
-        let color = LsColors::empty();
+        let opts = PrintOptions { color: LsColors::empty(), ..Default::default() };
+        assert_eq!(render(&dt, Some("root"), &opts), expected);
+    }
+
+    #[test]
+    fn test_classify() {
+        let mut dt = make_tree().unwrap();
+        dt.insert("exe", Entry::ExecFile).unwrap();
+        let expected = "\
+another_dir/
+└── some_file
+dev/
+├── initctl|
+└── null [1:3]
+exe*
+foo/
+├── bar
+├── baz@ -> symlink target
+├── subdir/
+└── subdir2/
+    └── subdir3/
+        └── subdir_file
+zed/
+├── asdf/
+│   └── ghjk
+├── b
+└── c => zed/b
+";
+        let opts = PrintOptions { color: LsColors::empty(), classify: true, ..Default::default() };
+        assert_eq!(render(&dt, None, &opts), expected);
+    }
+
+    #[test]
+    fn test_dir_counts() {
+        let expected = "\
+root
+├── another_dir [1 entry; 0 dirs, 1 file]
+│   └── some_file
+├── dev [2 entries; 0 dirs, 2 files]
+│   ├── initctl
+│   └── null [1:3]
+├── foo [4 entries; 3 dirs, 3 files]
+│   ├── bar
+│   ├── baz -> symlink target
+│   ├── subdir [0 entries; 0 dirs, 0 files]
+│   └── subdir2 [1 entry; 1 dir, 1 file]
+│       └── subdir3 [1 entry; 0 dirs, 1 file]
+│           └── subdir_file
+└── zed [3 entries; 1 dir, 3 files]
+    ├── asdf [1 entry; 0 dirs, 1 file]
+    │   └── ghjk
+    ├── b
+    └── c => zed/b
+";
+        let dt = make_tree().unwrap();
+        let opts =
+            PrintOptions { color: LsColors::empty(), dir_counts: true, ..Default::default() };
+        assert_eq!(render(&dt, Some("root"), &opts), expected);
+    }
+
+    #[test]
+    fn test_details() {
+        let mut dt = DirTree::default();
+        let acl = vec!["user::rwx".to_string(), "user:\x1b[31m:r-x".to_string()];
+        dt.insert("dir", Node::new(Entry::empty_dir(), EntryMeta { acl, ..Default::default() }))
+            .unwrap();
+        let xattrs = vec![Xattr::new("user.mime", "text/plain")];
+        dt.insert("dir/file", Node::new(Entry::File, EntryMeta { xattrs, ..Default::default() }))
+            .unwrap();
+        let expected = "\
+root
+└── dir
+    │  user::rwx
+    │  user:\\033[31m:r-x
+    └── file
+           user.mime: \"text/plain\"
+";
+        let opts = PrintOptions { color: LsColors::empty(), ..Default::default() };
+        assert_eq!(render(&dt, Some("root"), &opts), expected);
+    }
+
+    #[test]
+    fn test_encrypted() {
+        let mut dt = DirTree::default();
+        let encrypted = || EntryMeta { encrypted: true, ..Default::default() };
+        dt.insert("dir/secret", Node::new(Entry::File, encrypted())).unwrap();
+        dt.insert("dir/run", Node::new(Entry::ExecFile, encrypted())).unwrap();
+        dt.insert("link", Entry::symlink("dir/secret")).unwrap();
+        let expected = "\
+root
+├── dir
+│   ├── run [encrypted]
+│   └── secret [encrypted]
+└── link -> dir/secret
+";
+        let opts = PrintOptions { color: LsColors::empty(), ..Default::default() };
+        assert_eq!(render(&dt, Some("root"), &opts), expected);
+
+        // links and directories don't have data, so they don't count
+        assert!(dt.stats().all_encrypted());
+        dt.insert("plain", Entry::File).unwrap();
+        assert!(!dt.stats().all_encrypted());
+        assert!(!DirTree::default().stats().all_encrypted());
+    }
+
+    #[test]
+    fn test_sparse() {
+        let mut dt = DirTree::default();
+        let meta =
+            EntryMeta { size: Some(1 << 30), allocated_size: Some(8192), ..Default::default() };
+        dt.insert("disk.img", Node::new(Entry::File, meta)).unwrap();
+        dt.insert(
+            "plain",
+            Node::new(Entry::File, EntryMeta { size: Some(10), ..Default::default() }),
+        )
+        .unwrap();
+        let expected = "\
+root
+├── disk.img [sparse, 8.0 KiB of 1.0 GiB allocated]
+└── plain
+";
+        let opts = PrintOptions { color: LsColors::empty(), ..Default::default() };
+        assert_eq!(render(&dt, Some("root"), &opts), expected);
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut dt = DirTree::default();
+        let comment = Some("Don't \"quote\"\r".to_string());
+        dt.insert("file", Node::new(Entry::File, EntryMeta { comment, ..Default::default() }))
+            .unwrap();
+        let notes = ["a comment".to_string(), "\x1b[31mred\x07".to_string()];
+        let expected = "\
+root
+[a comment]
+[\\033[31mred\\a]
+└── file  # Don't \"quote\"\\r
+";
+        let mut opts = PrintOptions { color: LsColors::empty(), ..Default::default() };
+        let mut v = NoColor::new(Vec::<u8>::new());
+        dt.write_to(&mut v, Some("root"), &notes, &opts).unwrap();
+        assert_eq!(String::from_utf8(v.into_inner()).unwrap(), expected);
+
+        opts.quoting = QuotingStyle::Literal;
         let mut v = NoColor::new(Vec::<u8>::new());
+        dt.write_to(&mut v, Some("root"), &notes, &opts).unwrap();
+        let literal = String::from_utf8(v.into_inner()).unwrap();
+        assert!(literal.contains("[\x1b[31mred\x07]\n"));
+        assert!(literal.contains("# Don't \"quote\"\r\n"));
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut dt = DirTree::default();
+        dt.insert("dir/a_rather_long_file_name.txt", Entry::File).unwrap();
+        dt.insert("dir/ファイル名.txt", Entry::File).unwrap();
+        dt.insert("link", Entry::symlink("/a/long/symlink/target")).unwrap();
+        let expected = "\
+root
+├── dir
+│   ├── a_rather_long…
+│   └── ファイル名.txt
+└── link -> /a/long/s…
+";
+        let opts = PrintOptions { color: LsColors::empty(), width: Some(22), ..Default::default() };
+        assert_eq!(render(&dt, Some("root"), &opts), expected);
+    }
+
+    #[test]
+    fn test_ascii_guides() {
+        let expected = "\
+root
+|-- another_dir
+|   `-- some_file
+|-- dev
+|   |-- initctl
+|   `-- null [1:3]
+|-- foo
+|   |-- bar
+|   |-- baz -> symlink target
+|   |-- subdir
+|   `-- subdir2
+|       `-- subdir3
+|           `-- subdir_file
+`-- zed
+    |-- asdf
+    |   `-- ghjk
+    |-- b
+    `-- c => zed/b
+";
+        let dt = make_tree().unwrap();
+        let opts =
+            PrintOptions { color: LsColors::empty(), guides: Guides::ASCII, ..Default::default() };
+        assert_eq!(render(&dt, Some("root"), &opts), expected);
+    }
+
+    #[test]
+    fn test_no_indent() {
+        let expected = "\
+root
+another_dir
+some_file
+dev
+initctl
+null [1:3]
+foo
+bar
+baz -> symlink target
+subdir
+subdir2
+subdir3
+subdir_file
+zed
+asdf
+ghjk
+b
+c => zed/b
+";
+        let dt = make_tree().unwrap();
+        let opts = PrintOptions {
+            color: LsColors::empty(),
+            guides: Guides::NO_INDENT,
+            ..Default::default()
+        };
+        assert_eq!(render(&dt, Some("root"), &opts), expected);
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut dt = DirTree::default();
+        for (name, size) in [("lib-1.10", 10), ("lib-1.9", 900), ("lib-1.09.txt", 20), ("a", 10)] {
+            dt.insert(
+                name,
+                Node::new(Entry::File, EntryMeta { size: Some(size), ..Default::default() }),
+            )
+            .unwrap();
+        }
+        let sorted = |sort, reverse| {
+            let opts = PrintOptions { sort, reverse, ..Default::default() };
+            dt.sorted_entries(&opts)
+                .iter()
+                .map(|(name, _)| name.to_str().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(sorted(SortOrder::Name, false), ["a", "lib-1.09.txt", "lib-1.10", "lib-1.9"]);
+        assert_eq!(sorted(SortOrder::Version, false), ["a", "lib-1.9", "lib-1.09.txt", "lib-1.10"]);
+        assert_eq!(sorted(SortOrder::Size, false), ["lib-1.9", "lib-1.09.txt", "a", "lib-1.10"]);
+        assert_eq!(sorted(SortOrder::Size, true), ["lib-1.10", "a", "lib-1.09.txt", "lib-1.9"]);
+        assert_eq!(
+            sorted(SortOrder::Extension, false),
+            ["a", "lib-1.10", "lib-1.9", "lib-1.09.txt"]
+        );
+    }
+
+    #[test]
+    fn test_dir_placement() {
+        let dt = make_tree().unwrap();
+        let foo = match &dt.0[Path::new("foo")].entry {
+            Entry::Directory(dir) => dir,
+            _ => unreachable!(),
+        };
+        let ordered = |dir_placement, reverse| {
+            let opts = PrintOptions { dir_placement, reverse, ..Default::default() };
+            foo.sorted_entries(&opts)
+                .iter()
+                .map(|(name, _)| name.to_str().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ordered(DirPlacement::First, false), ["subdir", "subdir2", "bar", "baz"]);
+        assert_eq!(ordered(DirPlacement::First, true), ["subdir2", "subdir", "baz", "bar"]);
+        assert_eq!(ordered(DirPlacement::Last, false), ["bar", "baz", "subdir", "subdir2"]);
+    }
+
+    #[test]
+    fn test_full_path() {
+        let expected = "\
+root
+├── another_dir
+│   └── another_dir/some_file
+├── dev
+│   ├── dev/initctl
+│   └── dev/null [1:3]
+├── foo
+│   ├── foo/bar
+│   ├── foo/baz -> symlink target
+│   ├── foo/subdir
+│   └── foo/subdir2
+│       └── foo/subdir2/subdir3
+│           └── foo/subdir2/subdir3/subdir_file
+└── zed
+    ├── zed/asdf
+    │   └── zed/asdf/ghjk
+    ├── zed/b
+    └── zed/c => zed/b
+";
+        let dt = make_tree().unwrap();
+        let opts = PrintOptions { color: LsColors::empty(), full_path: true, ..Default::default() };
+        assert_eq!(render(&dt, Some("root"), &opts), expected);
+    }
+
+    #[test]
+    fn test_inode_columns() {
+        let mut dt = DirTree::default();
+        let meta = EntryMeta { inode: Some(1234567), device: Some(2049), ..Default::default() };
+        dt.insert("dir", Node::new(Entry::empty_dir(), meta.clone())).unwrap();
+        dt.insert("dir/file", Node::new(Entry::File, EntryMeta { inode: Some(42), ..meta }))
+            .unwrap();
+        dt.insert("dir/unknown", Entry::File).unwrap();
+        let expected = "\
+root
+└── [ 1234567 2049]  dir
+    ├── [      42 2049]  file
+    └── [       -    -]  unknown
+";
+        let opts = PrintOptions {
+            color: LsColors::empty(),
+            inodes: true,
+            device: true,
+            ..Default::default()
+        };
+        assert_eq!(render(&dt, Some("root"), &opts), expected);
+    }
+
+    #[test]
+    fn test_device_number() {
+        assert_eq!(DeviceNumber::from_dev(0x0103), DeviceNumber { major: 1, minor: 3 });
+        // major 259, minor 65536 using the extended 64-bit dev_t encoding
+        assert_eq!(DeviceNumber::from_dev(0x1001_0300), DeviceNumber { major: 259, minor: 65536 });
+    }
+
+    #[test]
+    fn test_resolve_symlinks() {
+        let mut dt = make_tree().unwrap();
+        let links = [
+            ("links/file", "../foo/bar", LinkState::Valid),
+            ("links/dir", "../foo/subdir2/./subdir3", LinkState::Valid),
+            ("links/chain", "dir/subdir_file", LinkState::Valid),
+            ("links/missing", "../foo/nope", LinkState::Broken),
+            ("links/not_a_dir", "../foo/bar/baz", LinkState::Broken),
+            ("links/loop", "loop", LinkState::Broken),
+            ("links/absolute", "/etc/passwd", LinkState::Unknown),
+            ("links/outside", "../../elsewhere", LinkState::Unknown),
+        ];
+        for (path, target, _) in links.iter() {
+            dt.insert(path, Entry::symlink(*target)).unwrap();
+        }
+        dt.resolve_symlinks();
+
+        for (path, _, expected) in links.iter() {
+            match dt.get(path).map(|node| &node.entry) {
+                Some(Entry::Symlink { state, .. }) => assert_eq!(state, expected, "{}", path),
+                other => panic!("unexpected entry for {}: {:?}", path, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = make_tree().unwrap().stats();
+        assert_eq!(
+            stats,
+            TreeStats {
+                dirs: 8,
+                files: 5,
+                symlinks: 1,
+                hardlinks: 1,
+                fifos: 1,
+                char_devices: 1,
+                size: 1024,
+                ..Default::default()
+            }
+        );
 
-        dt.write_to(&mut v, Some("root"), &color).unwrap();
-        let s = String::from_utf8(v.into_inner()).unwrap();
-        assert_eq!(s, expected);
+        let mut v = Vec::new();
+        stats.write_report(&mut v, true).unwrap();
+        assert_eq!(
+            String::from_utf8(v).unwrap(),
+            "8 directories, 5 files, 1 symlink, 1 hardlink, 1 fifo, 1 character device (1.0 KiB)\n"
+        );
     }
 }
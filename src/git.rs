@@ -0,0 +1,124 @@
+// Copyright (c) 2021 Allen Wild <allenwild93@gmail.com>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Git status of files in a work tree, from the output of `git status`.
+
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use termcolor::Color;
+
+/// Status of a file in a git work tree. Variants are ordered by how interesting they are, so that
+/// a directory can show the most interesting status of anything inside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GitStatus {
+    Ignored,
+    Untracked,
+    Added,
+    Modified,
+    Conflicted,
+}
+
+impl GitStatus {
+    /// Parse the two-letter XY status code from `git status --porcelain`
+    fn from_code(code: &[u8]) -> Option<Self> {
+        match code {
+            b"!!" => Some(Self::Ignored),
+            b"??" => Some(Self::Untracked),
+            b"DD" | b"AU" | b"UD" | b"UA" | b"DU" | b"AA" | b"UU" => Some(Self::Conflicted),
+            [b'A', _] => Some(Self::Added),
+            [_, _] => Some(Self::Modified),
+            _ => None,
+        }
+    }
+
+    /// Single character marker, like the short format of `git status`
+    pub fn marker(self) -> char {
+        match self {
+            Self::Ignored => '!',
+            Self::Untracked => '?',
+            Self::Added => 'A',
+            Self::Modified => 'M',
+            Self::Conflicted => 'U',
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            Self::Ignored => Color::Black,
+            Self::Untracked => Color::Magenta,
+            Self::Added => Color::Green,
+            Self::Modified => Color::Yellow,
+            Self::Conflicted => Color::Red,
+        }
+    }
+}
+
+/// Run git in `dir` and return its stdout, or None if git failed (e.g. because `dir` isn't in a
+/// work tree). Optional locks are disabled so that `git status` doesn't write to the index, since
+/// listing a directory shouldn't modify it.
+fn run_git(dir: &Path, args: &[&str]) -> io::Result<Option<Vec<u8>>> {
+    let output =
+        Command::new("git").arg("--no-optional-locks").arg("-C").arg(dir).args(args).output()?;
+    Ok(output.status.success().then_some(output.stdout))
+}
+
+/// Get the status of every changed, untracked, and ignored file in the work tree containing
+/// `dir`, with paths relative to `dir`. Files outside of `dir` are skipped, except that changes
+/// inside of `dir` but to files which no longer exist are still included. Returns None if `dir`
+/// isn't inside a git work tree.
+pub fn read_status(dir: &Path) -> io::Result<Option<Vec<(PathBuf, GitStatus)>>> {
+    let toplevel = match run_git(dir, &["rev-parse", "--show-toplevel"])? {
+        Some(out) => PathBuf::from(OsStr::from_bytes(out.strip_suffix(b"\n").unwrap_or(&out))),
+        None => return Ok(None),
+    };
+    let args = ["status", "--porcelain=v1", "-z", "--untracked-files=all", "--ignored=matching"];
+    let out = match run_git(dir, &args)? {
+        Some(out) => out,
+        None => return Ok(None),
+    };
+
+    let mut statuses = Vec::new();
+    // Entries are "XY path", separated by NULs. Renames and copies are followed by an extra
+    // field with the original path.
+    let mut fields = out.split(|&b| b == 0).filter(|field| !field.is_empty());
+    while let Some(field) = fields.next() {
+        if field.len() < 4 {
+            continue;
+        }
+        let (code, path) = (&field[..2], &field[3..]);
+        if matches!(code[0], b'R' | b'C') {
+            fields.next();
+        }
+        let status = match GitStatus::from_code(code) {
+            Some(status) => status,
+            None => continue,
+        };
+        // directories end with a slash
+        let path = Path::new(OsStr::from_bytes(path.strip_suffix(b"/").unwrap_or(path)));
+        if let Ok(rela) = toplevel.join(path).strip_prefix(dir) {
+            statuses.push((rela.to_owned(), status));
+        }
+    }
+    Ok(Some(statuses))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GitStatus;
+
+    #[test]
+    fn test_from_code() {
+        assert_eq!(GitStatus::from_code(b" M"), Some(GitStatus::Modified));
+        assert_eq!(GitStatus::from_code(b"R "), Some(GitStatus::Modified));
+        assert_eq!(GitStatus::from_code(b"AM"), Some(GitStatus::Added));
+        assert_eq!(GitStatus::from_code(b"UU"), Some(GitStatus::Conflicted));
+        assert_eq!(GitStatus::from_code(b"AA"), Some(GitStatus::Conflicted));
+        assert_eq!(GitStatus::from_code(b"??"), Some(GitStatus::Untracked));
+        assert_eq!(GitStatus::from_code(b"!!"), Some(GitStatus::Ignored));
+        assert!(GitStatus::Conflicted > GitStatus::Modified);
+    }
+}
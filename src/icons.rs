@@ -0,0 +1,186 @@
+// Copyright (c) 2021 Allen Wild <allenwild93@gmail.com>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Nerd Font icons for entries, chosen by file name, extension, or kind.
+
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+use crate::dir_tree::Entry;
+
+/// Icons for exact file names
+const NAME_ICONS: &[(&str, char)] = &[
+    (".git", '\u{e5fb}'),
+    (".gitignore", '\u{f1d3}'),
+    (".gitmodules", '\u{f1d3}'),
+    ("Cargo.lock", '\u{e7a8}'),
+    ("Cargo.toml", '\u{e7a8}'),
+    ("Dockerfile", '\u{f308}'),
+    ("LICENSE", '\u{f0219}'),
+    ("Makefile", '\u{f489}'),
+    ("README.md", '\u{f48a}'),
+];
+
+/// Icons for file extensions, which are matched case-insensitively
+const EXTENSION_ICONS: &[(&str, char)] = &[
+    ("7z", '\u{f410}'),
+    ("bz2", '\u{f410}'),
+    ("c", '\u{e61e}'),
+    ("cpp", '\u{e61d}'),
+    ("css", '\u{e749}'),
+    ("deb", '\u{e77d}'),
+    ("gif", '\u{f1c5}'),
+    ("go", '\u{e626}'),
+    ("gz", '\u{f410}'),
+    ("h", '\u{f0fd}'),
+    ("html", '\u{f13b}'),
+    ("iso", '\u{e271}'),
+    ("java", '\u{e738}'),
+    ("jpeg", '\u{f1c5}'),
+    ("jpg", '\u{f1c5}'),
+    ("js", '\u{e74e}'),
+    ("json", '\u{e60b}'),
+    ("lock", '\u{f023}'),
+    ("md", '\u{f48a}'),
+    ("pdf", '\u{f1c1}'),
+    ("png", '\u{f1c5}'),
+    ("py", '\u{e606}'),
+    ("rpm", '\u{e7bb}'),
+    ("rs", '\u{e7a8}'),
+    ("sh", '\u{f489}'),
+    ("so", '\u{f17c}'),
+    ("svg", '\u{f1c5}'),
+    ("tar", '\u{f410}'),
+    ("toml", '\u{e615}'),
+    ("ts", '\u{e628}'),
+    ("txt", '\u{f15c}'),
+    ("xz", '\u{f410}'),
+    ("yaml", '\u{e615}'),
+    ("yml", '\u{e615}'),
+    ("zip", '\u{f410}'),
+    ("zst", '\u{f410}'),
+];
+
+/// Icon lookup tables. Names take priority over extensions, which take priority over the icon
+/// for the entry's kind.
+#[derive(Debug, Clone)]
+pub struct Icons {
+    names: HashMap<String, char>,
+    extensions: HashMap<String, char>,
+    dir: char,
+    file: char,
+    exec: char,
+    symlink: char,
+    fifo: char,
+    socket: char,
+    device: char,
+}
+
+impl Default for Icons {
+    fn default() -> Self {
+        Self {
+            names: NAME_ICONS.iter().map(|&(name, icon)| (name.to_string(), icon)).collect(),
+            extensions: EXTENSION_ICONS
+                .iter()
+                .map(|&(ext, icon)| (ext.to_string(), icon))
+                .collect(),
+            dir: '\u{f115}',
+            file: '\u{f15b}',
+            exec: '\u{f489}',
+            symlink: '\u{f0c1}',
+            fifo: '\u{f0232}',
+            socket: '\u{f0427}',
+            device: '\u{f0a0}',
+        }
+    }
+}
+
+impl Icons {
+    /// Load the default icons, with any overrides from `$PINE_ICONS`.
+    pub fn from_env() -> Self {
+        let mut icons = Self::default();
+        if let Ok(spec) = env::var("PINE_ICONS") {
+            icons.apply_overrides(&spec);
+        }
+        icons
+    }
+
+    /// Apply overrides in a format like LS_COLORS: colon-separated `key=icon` pairs, where key is
+    /// one of the LS_COLORS kind codes (`di`, `fi`, `ex`, `ln`, `pi`, `so`, `bd`/`cd`), `*.ext`
+    /// for an extension, or otherwise an exact file name. An empty icon removes a name or
+    /// extension mapping. Malformed pairs are ignored.
+    pub fn apply_overrides(&mut self, spec: &str) {
+        for (key, value) in spec.split(':').filter_map(|pair| pair.split_once('=')) {
+            let icon = value.chars().next();
+            let kind_icon = match key {
+                "di" => &mut self.dir,
+                "fi" => &mut self.file,
+                "ex" => &mut self.exec,
+                "ln" => &mut self.symlink,
+                "pi" => &mut self.fifo,
+                "so" => &mut self.socket,
+                "bd" | "cd" => &mut self.device,
+                _ => {
+                    let (map, key) = match key.strip_prefix("*.") {
+                        Some(ext) => (&mut self.extensions, ext.to_lowercase()),
+                        None => (&mut self.names, key.to_string()),
+                    };
+                    match icon {
+                        Some(icon) => map.insert(key, icon),
+                        None => map.remove(&key),
+                    };
+                    continue;
+                }
+            };
+            if let Some(icon) = icon {
+                *kind_icon = icon;
+            }
+        }
+    }
+
+    /// Get the icon for an entry with the given name.
+    pub fn icon_for(&self, entry: &Entry, name: &Path) -> char {
+        let file_name = name.file_name().and_then(|n| n.to_str());
+        if let Some(&icon) = file_name.and_then(|n| self.names.get(n)) {
+            return icon;
+        }
+        match entry {
+            Entry::Directory(_) => self.dir,
+            Entry::Symlink { .. } => self.symlink,
+            Entry::Fifo => self.fifo,
+            Entry::Socket => self.socket,
+            Entry::BlockDevice(_) | Entry::CharDevice(_) => self.device,
+            Entry::File | Entry::ExecFile | Entry::Hardlink(_) => {
+                let ext = name.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+                match ext.and_then(|e| self.extensions.get(&e)) {
+                    Some(&icon) => icon,
+                    None if matches!(entry, Entry::ExecFile) => self.exec,
+                    None => self.file,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Icons;
+    use crate::dir_tree::Entry;
+
+    #[test]
+    fn test_icon_for() {
+        let mut icons = Icons::default();
+        icons.apply_overrides("di=D:ex=X:*.RS=R:*.md=:Makefile=M:bogus");
+
+        assert_eq!(icons.icon_for(&Entry::empty_dir(), Path::new("src")), 'D');
+        assert_eq!(icons.icon_for(&Entry::File, Path::new("src/main.rs")), 'R');
+        assert_eq!(icons.icon_for(&Entry::ExecFile, Path::new("run.rs")), 'R');
+        assert_eq!(icons.icon_for(&Entry::ExecFile, Path::new("run")), 'X');
+        assert_eq!(icons.icon_for(&Entry::File, Path::new("Makefile")), 'M');
+        assert_eq!(icons.icon_for(&Entry::File, Path::new("notes.md")), '\u{f15b}');
+        assert_eq!(icons.icon_for(&Entry::File, Path::new("x.TXT")), '\u{f15c}');
+    }
+}
@@ -1,17 +1,26 @@
 // Copyright (c) 2021 Allen Wild <allenwild93@gmail.com>
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::collections::hash_map::{Entry as HashEntry, HashMap};
+use std::fmt;
 use std::fs::{self, File, Metadata};
 use std::io::{self, Read, Seek, SeekFrom, Write};
-use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 
 use libarchive::ArchiveReader;
-use lscolors::LsColors;
 use termcolor::WriteColor;
 use walkdir::WalkDir;
 
-use crate::dir_tree::{DirTree, DirTreeError, DirTreeResult, Entry};
+use crate::checksum::Checksum;
+use crate::dir_tree::{
+    DeviceNumber, DirTree, DirTreeError, DirTreeResult, Entry, EntryMeta, LinkState, Node,
+    PrintOptions, TreeStats,
+};
+use crate::git::{self, GitStatus};
+use crate::magic;
+use crate::xattr::{self, Xattr};
+use crate::zip;
 
 /// Check whether a file's metadata is executable, i.e. whether any of the bits in
 /// `S_IXUSR | S_IXGRP | S_IXOTH` are set.
@@ -20,33 +29,165 @@ fn is_executable(meta: &Metadata) -> bool {
     (meta.permissions().mode() & 0o111) != 0
 }
 
+/// Get the Entry for a special file (FIFO, socket, or device node) from its metadata. Returns None
+/// for any other type of file.
+fn special_file_entry(meta: &Metadata) -> Option<Entry> {
+    let ftype = meta.file_type();
+    if ftype.is_fifo() {
+        Some(Entry::Fifo)
+    } else if ftype.is_socket() {
+        Some(Entry::Socket)
+    } else if ftype.is_block_device() {
+        Some(Entry::BlockDevice(DeviceNumber::from_dev(meta.rdev())))
+    } else if ftype.is_char_device() {
+        Some(Entry::CharDevice(DeviceNumber::from_dev(meta.rdev())))
+    } else {
+        None
+    }
+}
+
+/// Create a symlink Entry for the symlink at `path` on disk, reading its target and checking
+/// whether that target exists.
+fn read_symlink(path: &Path) -> Entry {
+    let target =
+        fs::read_link(path).unwrap_or_else(|_| PathBuf::from("[failed to read symlink target]"));
+    // fs::metadata follows symlinks, so it fails when the target is missing
+    let state = if fs::metadata(path).is_ok() { LinkState::Valid } else { LinkState::Broken };
+    Entry::Symlink { target, state }
+}
+
+/// Collect the EntryMeta fields we care about from a file on disk.
+fn fs_entry_meta(meta: &Metadata) -> EntryMeta {
+    EntryMeta {
+        size: if meta.is_file() { Some(meta.len()) } else { None },
+        inode: Some(meta.ino()),
+        device: Some(meta.dev()),
+        mtime: meta.modified().ok(),
+        ..Default::default()
+    }
+}
+
+/// Options that control what information is gathered when reading a tree from its source.
+#[derive(Debug, Default, Clone)]
+pub struct ReadOptions {
+    /// When reading a directory, show files which share a (device, inode) pair with a file seen
+    /// earlier as hardlinks to that first file.
+    pub detect_hardlinks: bool,
+    /// Read the contents of every file and compute its checksum with this algorithm.
+    pub checksum: Option<Checksum>,
+    /// Detect the type of every file from the first bytes of its contents.
+    pub magic: bool,
+    /// Look up the compressed size of each entry when reading a zip archive.
+    pub compression: bool,
+    /// Look up the comment of each entry when reading a zip archive.
+    pub comments: bool,
+    /// Read the extended attributes of every entry, other than ACLs.
+    pub xattrs: bool,
+    /// Read the access control list of every entry.
+    pub acls: bool,
+    /// When reading a directory in a git work tree, get the git status of every entry.
+    pub git: bool,
+}
+
+/// Sort extended attributes into the entry metadata according to `opts`. POSIX ACLs stored as
+/// xattrs are decoded into text and kept separately from the rest.
+fn add_xattrs(opts: &ReadOptions, xattrs: Vec<Xattr>, meta: &mut EntryMeta) {
+    for xattr in xattrs {
+        match xattr.posix_acl() {
+            Some(acl) if opts.acls => meta.acl.extend(acl),
+            Some(_) => (),
+            None if opts.xattrs => meta.xattrs.push(xattr),
+            None => (),
+        }
+    }
+}
+
+/// Read a file's data to fill in its checksum and detected type, if either is requested in
+/// `opts`. `open` is only called when the data is needed. Failures to read the data only print
+/// a warning, since they shouldn't prevent listing the rest of the tree.
+fn inspect_data<R, F>(opts: &ReadOptions, open: F, path: &Path, meta: &mut EntryMeta)
+where
+    R: Read,
+    F: FnOnce() -> io::Result<R>,
+{
+    // encrypted data can't be read without a passphrase, so don't try
+    if (opts.checksum.is_none() && !opts.magic) || meta.encrypted {
+        return;
+    }
+
+    let ret = open().and_then(|mut reader| {
+        // read the first chunk of data for sniffing, then chain it back in front of the rest of
+        // the reader for hashing
+        let mut head = Vec::with_capacity(magic::SNIFF_LEN);
+        (&mut reader).take(magic::SNIFF_LEN as u64).read_to_end(&mut head)?;
+        if opts.magic {
+            meta.magic = magic::sniff(&head);
+        }
+        if let Some(checksum) = opts.checksum {
+            meta.checksum = Some(checksum.hash_reader(head.as_slice().chain(reader))?);
+        }
+        Ok(())
+    });
+    if let Err(e) = ret {
+        eprintln!("warning: failed to read data for '{}': {}", path.display(), e);
+    }
+}
+
 /// The parsed directory tree, optionally with a custom root node name (if root is None, then tree
 /// usually has only one top-level directory entry)
 #[derive(Debug)]
 pub struct PineTree {
     pub tree: DirTree,
     pub root: Option<String>,
+    /// notes about the tree as a whole, printed below the root
+    pub notes: Vec<String>,
+    /// the detected format, if the tree was read from an archive
+    pub format: Option<ArchiveFormat>,
+    /// whether git statuses were loaded, so that status markers should be shown
+    pub git: bool,
 }
 
 impl PineTree {
     /// Create a PineTree from a filesystem path. If the path is a directory, then walk its
     /// contents. If the path is a file, assume it's an archive and load its contents using
     /// libarchive.
-    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, DirTreeError> {
+    pub fn from_path(path: impl AsRef<Path>, opts: &ReadOptions) -> Result<Self, DirTreeError> {
         let path = path.as_ref();
 
+        let mut notes = Vec::new();
+        let mut format = None;
+        let mut git = false;
         let (tree, root) = if path == Path::new("-") {
-            (read_from_archive(io::stdin(), |_| true)?, None)
+            let (tree, fmt) = read_from_archive(io::stdin(), |_| true, opts)?;
+            format = Some(fmt);
+            (tree, None)
         } else {
             let meta = std::fs::metadata(path)?;
             let tree = if meta.is_dir() {
-                read_from_filesystem(path)?
+                let mut tree = read_from_filesystem(path, opts)?;
+                git = opts.git && read_git_status(path, &mut tree);
+                tree
             } else {
-                read_from_archive_file(path, |_| true)?
+                let (mut tree, fmt) = read_from_archive_file(path, |_| true, opts)?;
+                format = Some(fmt);
+                if let Some(comment) = read_zip_directory(path, &mut tree, opts) {
+                    notes.extend(
+                        comment
+                            .lines()
+                            .map(str::trim_end)
+                            .filter(|l| !l.is_empty())
+                            .map(String::from),
+                    );
+                }
+                tree
             };
             (tree, Some(path.display().to_string()))
         };
-        Ok(Self { tree, root })
+
+        if tree.stats().all_encrypted() {
+            notes.push("all entries are encrypted".into());
+        }
+        Ok(Self { tree, root, notes, format, git })
     }
 
     /// Create a PineTree from a list of filenames, one per line. All leaf entries are assumed to
@@ -67,19 +208,23 @@ impl PineTree {
                 // try to stat the path and figure out what sort of file/entry it is
                 if let Ok(meta) = fs::symlink_metadata(line) {
                     let ftype = meta.file_type();
-                    if ftype.is_file() {
-                        let tree_entry =
-                            if is_executable(&meta) { Entry::ExecFile } else { Entry::File };
-                        tree.replace(line, tree_entry)?;
+                    let tree_entry = if ftype.is_file() {
+                        if is_executable(&meta) {
+                            Entry::ExecFile
+                        } else {
+                            Entry::File
+                        }
                     } else if ftype.is_dir() {
-                        tree.replace(line, Entry::empty_dir())?;
+                        Entry::empty_dir()
                     } else if ftype.is_symlink() {
-                        let target = fs::read_link(line)
-                            .unwrap_or_else(|_| PathBuf::from("[failed to read symlink target]"));
-                        tree.replace(line, Entry::Symlink(target))?;
+                        read_symlink(Path::new(line))
+                    } else if let Some(special) = special_file_entry(&meta) {
+                        special
                     } else {
-                        unreachable!();
-                    }
+                        eprintln!("warning: unknown file type for '{}', assuming File", line);
+                        Entry::File
+                    };
+                    tree.replace(line, Node::new(tree_entry, fs_entry_meta(&meta)))?;
                 } else {
                     // failed to stat the path, just assume it's a file
                     tree.replace(line, Entry::File)?;
@@ -88,7 +233,7 @@ impl PineTree {
                 tree.replace(line, Entry::File)?;
             }
         }
-        Ok(Self { tree, root: None })
+        Ok(Self { tree, root: None, notes: Vec::new(), format: None, git: false })
     }
 
     pub fn from_text_listing_path(
@@ -107,28 +252,85 @@ impl PineTree {
     }
 
     /// Print our DirTree to a stream. For archives, we have to specify the name of the root node.
-    pub fn print<W>(&self, w: &mut W, color: &LsColors) -> io::Result<()>
+    /// If enabled in `opts`, a summary report line is printed after the tree.
+    pub fn print<W>(&self, w: &mut W, opts: &PrintOptions) -> io::Result<()>
     where
         W: Write + WriteColor,
     {
-        match &self.root {
-            Some(root) => self.tree.print_with_root(w, root, color),
-            None => self.tree.print(w, color),
+        let format = self.format.as_ref().filter(|_| opts.archive_format);
+        match (&self.root, format) {
+            (Some(root), Some(format)) => {
+                let root = format!("{} ({})", root, format);
+                self.tree.print_with_root(w, &root, &self.notes, opts)?
+            }
+            (Some(root), None) => self.tree.print_with_root(w, root, &self.notes, opts)?,
+            (None, _) => {
+                // no root line to put the format on, so it goes first as another note
+                for note in format.map(ToString::to_string).iter().chain(&self.notes) {
+                    writeln!(w, "[{}]", opts.quoting.escape_text(note))?;
+                }
+                self.tree.print(w, opts)?
+            }
         }
+        if opts.report {
+            writeln!(w)?;
+            self.stats().write_report(w, opts.report_size)?;
+        }
+        Ok(())
+    }
+
+    /// Count the entries in the tree, see [`DirTree::stats`]
+    pub fn stats(&self) -> TreeStats {
+        self.tree.stats()
     }
 }
 
-fn read_from_filesystem(path: &Path) -> DirTreeResult {
+fn read_from_filesystem(path: &Path, opts: &ReadOptions) -> DirTreeResult {
     let abs_path = path.canonicalize()?;
     let mut dt = DirTree::default();
+    // map of (device, inode) -> relative path for files with more than one link
+    let mut seen_inodes: HashMap<(u64, u64), PathBuf> = HashMap::new();
 
-    for entry in WalkDir::new(&abs_path).min_depth(1) {
+    // Sort entries by name so that the "first" of a set of hardlinks is the one that comes first
+    // in the tree.
+    for entry in WalkDir::new(&abs_path).min_depth(1).sort_by_file_name() {
         let entry = entry.map_err(|e| DirTreeError::IOError(e.into()))?;
 
+        // since we gave walkdir an absolute path, all the entries will have absolute paths too.
+        // Strip off the original path prefix and only include subdirectories in the tree.
+        let rela_path = entry.path().strip_prefix(&abs_path).unwrap_or_else(|_| {
+            // ugly warning, but I want details if this fails (because it should always work)
+            let entry_path = entry.path();
+            eprintln!(
+                "WARNING: failed to strip abs_path prefix '{}' from entry path '{}'",
+                abs_path.display(),
+                entry_path.display(),
+            );
+            entry_path
+        });
+
         let filetype = entry.file_type();
+        // walkdir doesn't follow symlinks by default, so this is lstat() metadata
+        let meta = entry.metadata().ok();
+        let mut entry_meta = meta.as_ref().map(fs_entry_meta).unwrap_or_default();
+        entry_meta.abs_path = Some(entry.path().to_owned());
         let tree_entry = if filetype.is_file() {
-            if let Ok(meta) = entry.metadata() {
-                if is_executable(&meta) {
+            if let Some(meta) = meta {
+                let first_link = if opts.detect_hardlinks && meta.nlink() > 1 {
+                    match seen_inodes.entry((meta.dev(), meta.ino())) {
+                        HashEntry::Occupied(first) => Some(first.get().clone()),
+                        HashEntry::Vacant(slot) => {
+                            slot.insert(rela_path.to_owned());
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                if let Some(target) = first_link {
+                    Entry::Hardlink(target)
+                } else if is_executable(&meta) {
                     Entry::ExecFile
                 } else {
                     Entry::File
@@ -137,48 +339,117 @@ fn read_from_filesystem(path: &Path) -> DirTreeResult {
                 Entry::File
             }
         } else if filetype.is_symlink() {
-            Entry::Symlink(PathBuf::from(entry.file_name()))
+            read_symlink(entry.path())
         } else if filetype.is_dir() {
             Entry::empty_dir()
+        } else if let Some(special) = meta.as_ref().and_then(special_file_entry) {
+            special
         } else {
-            unreachable!()
+            eprintln!("warning: unknown file type for '{}', assuming File", entry.path().display());
+            Entry::File
         };
 
-        // since we gave walkdir an absolute path, all the entries will have absolute paths too.
-        // Strip off the original path prefix and only include subdirectories in the tree.
-        let rela_path = entry.path().strip_prefix(&abs_path).unwrap_or_else(|_| {
-            // ugly warning, but I want details if this fails (because it should always work)
-            let entry_path = entry.path();
-            eprintln!(
-                "WARNING: failed to strip abs_path prefix '{}' from entry path '{}'",
-                abs_path.display(),
-                entry_path.display(),
-            );
-            entry_path
-        });
-        dt.insert(rela_path, tree_entry)?;
+        if matches!(tree_entry, Entry::File | Entry::ExecFile) {
+            inspect_data(opts, || File::open(entry.path()), entry.path(), &mut entry_meta);
+        }
+        if opts.xattrs || opts.acls {
+            match xattr::read_xattrs(entry.path()) {
+                Ok(xattrs) => add_xattrs(opts, xattrs, &mut entry_meta),
+                Err(e) => eprintln!(
+                    "warning: failed to read xattrs for '{}': {}",
+                    entry.path().display(),
+                    e
+                ),
+            }
+        }
+
+        dt.insert(rela_path, Node::new(tree_entry, entry_meta))?;
     }
 
     Ok(dt)
 }
 
+/// Load the git status of everything in the tree read from the directory at `path`. Returns
+/// whether any statuses were loaded, i.e. whether the directory is in a git work tree.
+fn read_git_status(path: &Path, dt: &mut DirTree) -> bool {
+    match path.canonicalize().and_then(|abs_path| git::read_status(&abs_path)) {
+        Ok(Some(statuses)) => {
+            apply_git_status(dt, statuses);
+            true
+        }
+        Ok(None) => {
+            eprintln!("warning: '{}' is not in a git work tree", path.display());
+            false
+        }
+        Err(e) => {
+            eprintln!("warning: failed to run git: {}", e);
+            false
+        }
+    }
+}
+
+/// Set the git status of entries in the tree. Directories get the most interesting status of
+/// anything inside them, except that ignored files don't make their parent ignored. Everything
+/// inside an ignored or untracked directory gets that status too.
+fn apply_git_status(dt: &mut DirTree, statuses: Vec<(PathBuf, GitStatus)>) {
+    fn update(node: &mut Node, status: GitStatus) {
+        node.meta.git = node.meta.git.max(Some(status));
+    }
+
+    for (path, status) in statuses {
+        if let Some(node) = dt.get_mut(&path) {
+            update(node, status);
+            if let Entry::Directory(dir) = &mut node.entry {
+                dir.for_each_mut(&mut |child| update(child, status));
+            }
+        }
+        if status != GitStatus::Ignored {
+            // deleted files aren't in the tree, but their parents are
+            for parent in path.ancestors().skip(1).filter(|p| !p.as_os_str().is_empty()) {
+                if let Some(node) = dt.get_mut(parent) {
+                    update(node, status);
+                }
+            }
+        }
+    }
+}
+
+/// The format and filters of an archive as detected by libarchive.
+#[derive(Debug, Default, Clone)]
+pub struct ArchiveFormat {
+    pub name: Option<String>,
+    pub filters: Vec<String>,
+}
+
+impl fmt::Display for ArchiveFormat {
+    /// Formats as a comma-separated list, e.g. "POSIX pax interchange format, zstd"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<&str> =
+            self.name.iter().chain(self.filters.iter()).map(String::as_str).collect();
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// Result of reading an archive: the tree of entries and what kind of archive it was.
+pub type ArchiveResult = Result<(DirTree, ArchiveFormat), DirTreeError>;
+
 /// Load a DirTree from the libarchive-supported archive stream returned by the reader.
 ///
 /// The `filter` function is called on the full path of every entry in the archive, if it returns
 /// false than that entry is skipped. No special handling is done to skip children of directories,
 /// the filter function must take care of that if needed.
-pub fn read_from_archive<R, F>(reader: R, filter: F) -> DirTreeResult
+pub fn read_from_archive<R, F>(reader: R, filter: F, opts: &ReadOptions) -> ArchiveResult
 where
     R: Read,
     F: Fn(&Path) -> bool,
 {
-    impl_read_from_archive(ArchiveReader::new(reader)?, filter)
+    impl_read_from_archive(ArchiveReader::new(reader)?, filter, opts)
 }
 
 /// Load a DirTree from the libarchive-supported archive file at path.
 ///
 /// The `filter` works in the same way as [`read_from_archive_with_filter`]
-pub fn read_from_archive_file<F>(path: &Path, filter: F) -> DirTreeResult
+pub fn read_from_archive_file<F>(path: &Path, filter: F, opts: &ReadOptions) -> ArchiveResult
 where
     F: Fn(&Path) -> bool,
 {
@@ -188,12 +459,58 @@ where
     // needed for some formats like 7-zip.
     #[allow(clippy::seek_from_current)]
     match file.seek(SeekFrom::Current(0)) {
-        Ok(_) => impl_read_from_archive(ArchiveReader::new_seekable(file)?, filter),
-        Err(_) => impl_read_from_archive(ArchiveReader::new(file)?, filter),
+        Ok(_) => impl_read_from_archive(ArchiveReader::new_seekable(file)?, filter, opts),
+        Err(_) => impl_read_from_archive(ArchiveReader::new(file)?, filter, opts),
     }
 }
 
-fn impl_read_from_archive<R, F>(mut archive: ArchiveReader<R>, filter: F) -> DirTreeResult
+/// Read the central directory of a zip file for the things libarchive doesn't give us: compressed
+/// sizes and entry comments are filled into the tree if requested in `opts`, and the archive
+/// comment is returned. Files that aren't zips are left alone, and errors only produce a warning
+/// since the listing itself is still fine.
+fn read_zip_directory(path: &Path, dt: &mut DirTree, opts: &ReadOptions) -> Option<String> {
+    let ret = File::open(path).and_then(|mut file| {
+        // only look for a central directory in files that start like a zip, since searching
+        // the end of any other file could give false positives
+        let mut sig = [0u8; 4];
+        match file.read_exact(&mut sig) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        match &sig {
+            b"PK\x03\x04" | b"PK\x05\x06" => zip::read_directory(&mut file),
+            _ => Ok(None),
+        }
+    });
+    let dir = match ret {
+        Ok(Some(dir)) => dir,
+        Ok(None) => return None,
+        Err(err) => {
+            eprintln!("WARN: failed to read zip directory from '{}': {}", path.display(), err);
+            return None;
+        }
+    };
+
+    for entry in dir.entries {
+        if let Some(node) = dt.get_mut(&entry.path) {
+            if opts.compression {
+                node.meta.compressed_size = Some(entry.compressed_size);
+                node.meta.size.get_or_insert(entry.size);
+            }
+            if opts.comments && !entry.comment.is_empty() {
+                node.meta.comment = Some(entry.comment);
+            }
+        }
+    }
+    Some(dir.comment).filter(|comment| !comment.is_empty())
+}
+
+fn impl_read_from_archive<R, F>(
+    mut archive: ArchiveReader<R>,
+    filter: F,
+    opts: &ReadOptions,
+) -> ArchiveResult
 where
     R: Read,
     F: Fn(&Path) -> bool,
@@ -203,7 +520,13 @@ where
         let entry = match archive.read_next_header() {
             Ok(Some(entry)) => entry,
             Ok(None) => break,
-            Err(e) => return Err(e.into()),
+            Err(e) => {
+                // formats like 7-zip can encrypt the headers too, which libarchive can't read
+                if archive.has_encrypted_entries() == Some(true) {
+                    return Err(e.context("archive is encrypted").into());
+                }
+                return Err(e.into());
+            }
         };
 
         let entry_path = entry
@@ -214,7 +537,11 @@ where
             continue;
         }
 
-        let tree_entry = if entry.is_exec_file() {
+        // hardlinks look like regular files (tar) or have no type at all (cpio), so check for them
+        // before anything else
+        let tree_entry = if let Some(target) = entry.hardlink_path() {
+            Entry::Hardlink(target)
+        } else if entry.is_exec_file() {
             Entry::ExecFile
         } else if entry.is_file() {
             Entry::File
@@ -225,9 +552,21 @@ where
                     entry_path.display()
                 ))
             })?;
-            Entry::Symlink(symlink_path)
+            Entry::symlink(symlink_path)
         } else if entry.is_dir() {
             Entry::empty_dir()
+        } else if entry.is_fifo() {
+            Entry::Fifo
+        } else if entry.is_socket() {
+            Entry::Socket
+        } else if entry.is_block_device() || entry.is_char_device() {
+            let (major, minor) = entry.rdev();
+            let dev = DeviceNumber { major, minor };
+            if entry.is_block_device() {
+                Entry::BlockDevice(dev)
+            } else {
+                Entry::CharDevice(dev)
+            }
         } else {
             eprintln!(
                 "warning: unknown type/mode {:03o} for entry '{}', assuming File",
@@ -237,8 +576,133 @@ where
             Entry::File
         };
 
-        dt.insert(entry_path, tree_entry)?;
+        let mut entry_meta = EntryMeta {
+            size: entry.size(),
+            mtime: entry.mtime(),
+            encrypted: entry.is_encrypted(),
+            ..Default::default()
+        };
+        let sparse_map = entry.sparse_map();
+        if !sparse_map.is_empty() {
+            entry_meta.allocated_size = Some(sparse_map.iter().map(|(_, len)| len).sum());
+        }
+        if opts.xattrs || opts.acls {
+            let xattrs = entry.xattrs().into_iter().map(|(name, value)| Xattr::new(name, value));
+            add_xattrs(opts, xattrs.collect(), &mut entry_meta);
+        }
+        if opts.acls {
+            if let Some(acl) = entry.acl_text() {
+                entry_meta.acl.extend(acl.lines().map(String::from));
+            }
+        }
+        // the borrow of entry ends here, so now we can read its data
+        if matches!(tree_entry, Entry::File | Entry::ExecFile) {
+            inspect_data(opts, || Ok(archive.data()), &entry_path, &mut entry_meta);
+        }
+        dt.insert(entry_path, Node::new(tree_entry, entry_meta))?;
     }
 
-    Ok(dt)
+    // now that the whole tree is loaded, we can check which symlinks point to something in it
+    dt.resolve_symlinks();
+    let format = ArchiveFormat { name: archive.format_name(), filters: archive.filter_names() };
+    Ok((dt, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    use super::{inspect_data, read_from_archive, read_from_filesystem, ReadOptions};
+    use crate::checksum::Checksum;
+    use crate::dir_tree::{DirTree, Entry, EntryMeta};
+    use crate::zip::testing::{build_zip, TestEntry};
+
+    /// Build an uncompressed ustar archive from (name, typeflag, link name, data) entries.
+    fn build_tar(entries: &[(&str, u8, &str, &[u8])]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        for &(name, typeflag, linkname, data) in entries {
+            let mut header = [0u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..108].copy_from_slice(b"0000644\0");
+            header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+            header[136..148].copy_from_slice(b"00000000000\0");
+            header[156] = typeflag;
+            header[157..157 + linkname.len()].copy_from_slice(linkname.as_bytes());
+            header[257..265].copy_from_slice(b"ustar\x0000");
+            // the checksum is computed with its own field set to spaces
+            header[148..156].copy_from_slice(b"        ");
+            let sum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+            header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+            tar.extend_from_slice(&header);
+            tar.extend_from_slice(data);
+            tar.resize(tar.len().next_multiple_of(512), 0);
+        }
+        tar.resize(tar.len() + 1024, 0);
+        tar
+    }
+
+    #[test]
+    fn test_archive_hardlinks() {
+        let tar = build_tar(&[("dir/a", b'0', "", b"data"), ("b", b'1', "dir/a", b"")]);
+        let (dt, _) = read_from_archive(tar.as_slice(), |_| true, &ReadOptions::default()).unwrap();
+
+        assert!(matches!(dt.get("dir/a").unwrap().entry, Entry::File));
+        match &dt.get("b").unwrap().entry {
+            Entry::Hardlink(target) => assert_eq!(target, Path::new("dir/a")),
+            other => panic!("unexpected entry for b: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encrypted_entries() {
+        let entries = [
+            TestEntry { name: "secret.txt", data: b"hello", encrypted: true, ..Default::default() },
+            TestEntry { name: "plain.txt", data: b"hello", ..Default::default() },
+        ];
+        let zip = build_zip(&entries, b"");
+        let opts = ReadOptions { checksum: Some(Checksum::Sha256), ..Default::default() };
+        let (dt, _) = read_from_archive(zip.as_slice(), |_| true, &opts).unwrap();
+
+        let plain = &dt.get("plain.txt").unwrap().meta;
+        assert!(!plain.encrypted);
+        assert!(plain.checksum.is_some());
+        let secret = &dt.get("secret.txt").unwrap().meta;
+        assert!(secret.encrypted);
+        assert_eq!(secret.checksum, None);
+
+        // the data of encrypted entries isn't read at all
+        let mut meta = EntryMeta { encrypted: true, ..Default::default() };
+        let open = || -> io::Result<&[u8]> { panic!("tried to read encrypted data") };
+        inspect_data(&opts, open, Path::new("secret.txt"), &mut meta);
+    }
+
+    #[test]
+    fn test_filesystem_hardlinks() {
+        let dir = std::env::temp_dir().join(format!("pine-test-hardlinks-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("b"), "data").unwrap();
+        fs::hard_link(dir.join("b"), dir.join("a")).unwrap();
+        fs::hard_link(dir.join("b"), dir.join("sub/c")).unwrap();
+        fs::write(dir.join("d"), "data").unwrap();
+
+        let opts = ReadOptions { detect_hardlinks: true, ..Default::default() };
+        let dt = read_from_filesystem(&dir, &opts);
+        let plain = read_from_filesystem(&dir, &ReadOptions::default());
+        fs::remove_dir_all(&dir).unwrap();
+        let (dt, plain) = (dt.unwrap(), plain.unwrap());
+
+        // the first link in name order is the file, and later ones point to it
+        fn entry<'a>(dt: &'a DirTree, path: &str) -> &'a Entry {
+            &dt.get(path).unwrap().entry
+        }
+        assert!(matches!(entry(&dt, "a"), Entry::File));
+        assert!(matches!(entry(&dt, "b"), Entry::Hardlink(target) if target == Path::new("a")));
+        assert!(matches!(entry(&dt, "sub/c"), Entry::Hardlink(target) if target == Path::new("a")));
+        assert!(matches!(entry(&dt, "d"), Entry::File));
+        for path in ["a", "b", "sub/c", "d"] {
+            assert!(matches!(entry(&plain, path), Entry::File), "{}", path);
+        }
+    }
 }
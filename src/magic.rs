@@ -0,0 +1,137 @@
+// Copyright (c) 2021 Allen Wild <allenwild93@gmail.com>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Minimal file type detection by looking at the first few bytes of a file, similar to (but much
+//! less thorough than) `file(1)`.
+
+/// How many bytes from the start of a file are needed by `sniff`. This covers the tar header
+/// magic at offset 257.
+pub const SNIFF_LEN: usize = 512;
+
+/// Magic byte signatures that appear at the start of a file, and their short type names.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x7fELF", "ELF"),
+    (b"\xfe\xed\xfa\xce", "Mach-O"),
+    (b"\xfe\xed\xfa\xcf", "Mach-O"),
+    (b"\xce\xfa\xed\xfe", "Mach-O"),
+    (b"\xcf\xfa\xed\xfe", "Mach-O"),
+    (b"\0asm", "WebAssembly"),
+    (b"\x1f\x8b", "gzip"),
+    (b"BZh", "bzip2"),
+    (b"\xfd7zXZ\0", "xz"),
+    (b"\x28\xb5\x2f\xfd", "zstd"),
+    (b"\x04\x22\x4d\x18", "lz4"),
+    (b"PK\x03\x04", "zip"),
+    (b"PK\x05\x06", "zip"),
+    (b"7z\xbc\xaf\x27\x1c", "7-zip"),
+    (b"Rar!\x1a\x07", "RAR"),
+    (b"!<arch>\n", "ar archive"),
+    (b"\x89PNG\r\n\x1a\n", "PNG"),
+    (b"\xff\xd8\xff", "JPEG"),
+    (b"GIF87a", "GIF"),
+    (b"GIF89a", "GIF"),
+    (b"%PDF-", "PDF"),
+    (b"SQLite format 3\0", "SQLite"),
+];
+
+/// Guess a short type name for a file based on the first bytes of its data, which should be
+/// at least `SNIFF_LEN` bytes long unless the file is shorter than that. Returns None if the type
+/// isn't recognized.
+pub fn sniff(data: &[u8]) -> Option<&'static str> {
+    if let Some((_, name)) = SIGNATURES.iter().find(|(sig, _)| data.starts_with(sig)) {
+        return Some(name);
+    }
+
+    if data.starts_with(b"MZ") {
+        return sniff_dos_exe(data);
+    }
+    if data.starts_with(b"\xca\xfe\xba\xbe") {
+        // Universal Mach-O binaries and Java class files share a magic number. Mach-O has
+        // a small count of architectures next, where Java has its version number (45 or higher).
+        let next = u32::from_be_bytes(data.get(4..8)?.try_into().ok()?);
+        return Some(if next < 45 { "Mach-O" } else { "Java class" });
+    }
+    if data.get(257..262) == Some(b"ustar") {
+        return Some("tar");
+    }
+    if data.starts_with(b"#!") {
+        return Some(sniff_script(data));
+    }
+    None
+}
+
+/// Distinguish PE executables from plain DOS ones by following the `e_lfanew` offset in the DOS
+/// header and checking for the "PE\0\0" signature. Returns None if the data is too short to hold
+/// a DOS header, since then it's more likely some other file that happens to start with "MZ".
+fn sniff_dos_exe(data: &[u8]) -> Option<&'static str> {
+    let pe_offset = u32::from_le_bytes(data.get(0x3c..0x40)?.try_into().ok()?) as usize;
+    match data.get(pe_offset..pe_offset.saturating_add(4)) {
+        Some(b"PE\0\0") => Some("PE"),
+        // if the data was cut off, the PE header may be past the end of what we read
+        None if data.len() >= SNIFF_LEN => Some("PE"),
+        _ => Some("DOS executable"),
+    }
+}
+
+/// Name a script by the interpreter in its shebang line.
+fn sniff_script(data: &[u8]) -> &'static str {
+    let line = data[2..].split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = String::from_utf8_lossy(line);
+    let mut words = line.split_whitespace();
+    // the interpreter is the basename of the first word, or the second word for `/usr/bin/env foo`
+    let mut interp = words.next().unwrap_or_default().rsplit('/').next().unwrap_or_default();
+    if interp == "env" {
+        interp = words.find(|w| !w.starts_with('-')).unwrap_or_default();
+    }
+
+    match interp.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "sh" | "bash" | "dash" | "ash" | "ksh" | "zsh" => "shell script",
+        "python" => "Python script",
+        "perl" => "Perl script",
+        "ruby" => "Ruby script",
+        "node" | "nodejs" => "Node.js script",
+        "lua" => "Lua script",
+        _ => "script",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sniff, SNIFF_LEN};
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\x7fELF\x02\x01\x01"), Some("ELF"));
+        assert_eq!(sniff(b"\x1f\x8b\x08\0"), Some("gzip"));
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0"), Some("PNG"));
+        assert_eq!(sniff(b"\xca\xfe\xba\xbe\0\0\0\x02"), Some("Mach-O"));
+        assert_eq!(sniff(b"\xca\xfe\xba\xbe\0\0\0\x34"), Some("Java class"));
+        assert_eq!(sniff(b"#!/bin/sh\necho hi\n"), Some("shell script"));
+        assert_eq!(sniff(b"#!/usr/bin/env -S python3.11 -u\n"), Some("Python script"));
+        assert_eq!(sniff(b"#! /usr/bin/node\n"), Some("Node.js script"));
+        assert_eq!(sniff(b"#!/usr/bin/awk -f\n"), Some("script"));
+        assert_eq!(sniff(b"just some text"), None);
+        assert_eq!(sniff(b""), None);
+
+        let mut pe = vec![0u8; 0x88];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c] = 0x80;
+        pe[0x80..0x84].copy_from_slice(b"PE\0\0");
+        assert_eq!(sniff(&pe), Some("PE"));
+        pe[0x80] = b'X';
+        assert_eq!(sniff(&pe), Some("DOS executable"));
+        pe[0x3c..0x40].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert_eq!(sniff(&pe), Some("DOS executable"));
+        let mut long_pe = pe.clone();
+        long_pe.resize(SNIFF_LEN, 0);
+        assert_eq!(sniff(&long_pe), Some("PE"));
+        pe[0x3c..0x40].copy_from_slice(&0x84u32.to_le_bytes());
+        assert_eq!(sniff(&pe), Some("DOS executable"));
+        assert_eq!(sniff(b"MZ!"), None);
+        assert_eq!(sniff(&pe[..0x3e]), None);
+
+        let mut tar = vec![0u8; 512];
+        tar[257..263].copy_from_slice(b"ustar\0");
+        assert_eq!(sniff(&tar), Some("tar"));
+    }
+}
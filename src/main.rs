@@ -14,12 +14,23 @@ use libc::c_int;
 use lscolors::LsColors;
 use termcolor::{ColorChoice, StandardStream};
 
+mod checksum;
 mod dir_tree;
+mod git;
+mod icons;
 mod input;
+mod magic;
 mod package;
+mod quote;
 mod util;
+mod xattr;
+mod zip;
 
-use crate::input::PineTree;
+use crate::checksum::Checksum;
+use crate::dir_tree::{DirPlacement, Guides, PrintOptions, SortOrder, TreeStats};
+use crate::icons::Icons;
+use crate::input::{PineTree, ReadOptions};
+use crate::quote::QuotingStyle;
 
 #[derive(Debug)]
 enum InputMode {
@@ -32,7 +43,25 @@ enum InputMode {
 struct Args {
     color_choice: ColorChoice,
     pager: bool,
+    report: bool,
+    report_size: bool,
+    classify: bool,
+    full_path: bool,
+    inodes: bool,
+    device: bool,
+    dir_counts: bool,
+    archive_format: bool,
+    git: bool,
+    width: Option<usize>,
+    hyperlink: bool,
+    icons: bool,
+    guides: Guides,
+    quoting: QuotingStyle,
+    sort: SortOrder,
+    reverse: bool,
+    dir_placement: DirPlacement,
     input_mode: InputMode,
+    read_opts: ReadOptions,
     inputs: Vec<OsString>,
 }
 
@@ -62,6 +91,92 @@ fn parse_args() -> Args {
                 .action(ArgAction::SetTrue)
                 .help("Alias for --color=always."),
         )
+        .arg(
+            Arg::new("hyperlink")
+                .long("hyperlink")
+                .value_parser(["auto", "always", "never"])
+                .default_value("never")
+                .default_missing_value("always")
+                .num_args(0..=1)
+                .require_equals(true)
+                .help("Make file names into hyperlinks, for terminals which support them.")
+                .long_help(
+                    "Make the names of files on disk into OSC 8 hyperlinks to their file:// \
+                     URLs, like `ls --hyperlink`. With 'auto', links are only used when writing \
+                     to a terminal.",
+        ))
+        .arg(
+            Arg::new("charset")
+                .long("charset")
+                .value_parser(["ascii", "utf8", "rounded", "heavy"])
+                .help("Characters used to draw the tree.")
+                .long_help(
+                    "Characters used to draw the tree lines. The default is utf8 if the locale \
+                     uses UTF-8, and ascii otherwise.",
+        ))
+        .arg(
+            Arg::new("indent")
+                .long("indent")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["charset", "no_indent"])
+                .help("Indent entries with spaces instead of drawing tree lines."),
+        )
+        .arg(
+            Arg::new("no_indent")
+                .long("no-indent")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("charset")
+                .help("Print all entries flush left without tree lines, like `tree -i`."),
+        )
+        .arg(
+            Arg::new("sort")
+                .long("sort")
+                .value_name("order")
+                .value_parser(["name", "version", "size", "mtime", "extension"])
+                .help("Order of the entries in each directory (default: name).")
+                .long_help(
+                    "Order of the entries in each directory. 'name' sorts byte-wise, 'version' \
+                     sorts numbers in names by their value like `sort -V`, 'size' puts the \
+                     largest files first, 'mtime' puts the newest entries first, and 'extension' \
+                     sorts by file extension. Ties are broken by name.",
+                ),
+        )
+        .arg(
+            Arg::new("reverse")
+                .short('r')
+                .long("reverse")
+                .action(ArgAction::SetTrue)
+                .help("Reverse the sort order."),
+        )
+        .arg(
+            Arg::new("dirsfirst")
+                .long("dirsfirst")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("filesfirst")
+                .help("List directories before other entries, like `tree --dirsfirst`."),
+        )
+        .arg(
+            Arg::new("filesfirst")
+                .long("filesfirst")
+                .action(ArgAction::SetTrue)
+                .help("List directories after other entries."),
+        )
+        .arg(
+            Arg::new("quoting_style")
+                .long("quoting-style")
+                .value_name("style")
+                .value_parser(["literal", "escape", "c", "shell"])
+                .help("How to quote names, like `ls` (default: escape).")
+                .long_help(
+                    "How to quote names and link targets. 'escape' shows control characters, \
+                     invalid UTF-8, and backslashes as C-style backslash escapes, so that names \
+                     from untrusted archives can't send escape sequences to the terminal. 'c' \
+                     also puts names in double quotes, 'shell' quotes names that need it for a \
+                     POSIX shell, and 'literal' prints names unmodified. Comments, notes, \
+                     ACLs, and xattr names only have control characters escaped, except with \
+                     'literal'.",
+                ),
+        )
         .arg(
             Arg::new("pager")
                 .short('P')
@@ -99,6 +214,166 @@ fn parse_args() -> Args {
                      checking the files on disk. Note this will call lstat() on each line of input. \
                      Non-absolute paths will be resolved relative to the current working directory.",
         ))
+        .arg(
+            Arg::new("classify")
+                .long("classify")
+                .action(ArgAction::SetTrue)
+                .help("Append a type indicator (one of */@|=) to entry names, like `ls -F`."),
+        )
+        .arg(
+            Arg::new("full_path")
+                .short('f')
+                .long("full-path")
+                .action(ArgAction::SetTrue)
+                .help("Print the full path of each entry from the root of the tree."),
+        )
+        .arg(
+            Arg::new("inodes")
+                .long("inodes")
+                .action(ArgAction::SetTrue)
+                .help("Show the inode number of each file on disk."),
+        )
+        .arg(
+            Arg::new("device")
+                .long("device")
+                .action(ArgAction::SetTrue)
+                .help("Show the ID of the device containing each file on disk."),
+        )
+        .arg(
+            Arg::new("dir_counts")
+                .long("counts")
+                .action(ArgAction::SetTrue)
+                .help("Show the number of entries in each directory.")
+                .long_help(
+                    "Show the number of entries directly inside each directory, followed by the \
+                     total number of directories and files below it at any depth.",
+        ))
+        .arg(
+            Arg::new("archive_format")
+                .long("archive-format")
+                .action(ArgAction::SetTrue)
+                .help("Show the detected format and compression of archives next to the root.")
+                .long_help(
+                    "Show the archive format and compression filters detected by libarchive next \
+                     to the root, e.g. 'foo.tar.zst (POSIX pax interchange format, zstd)'.",
+        ))
+        .arg(
+            Arg::new("hardlinks")
+                .long("hardlinks")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["package", "text_listing"])
+                .help("Show files that are hard links to an earlier file in the directory.")
+                .long_help(
+                    "When listing a directory, show files which share an inode with a file listed \
+                     earlier as hard links to that first file. Hard links are always shown when \
+                     listing archives.",
+        ))
+        .arg(
+            Arg::new("checksum")
+                .long("checksum")
+                .value_parser(["sha256", "md5", "blake3"])
+                .conflicts_with_all(["package", "text_listing"])
+                .help("Print a checksum of each file's contents.")
+                .long_help(
+                    "Print a checksum of each file's contents using the given algorithm. This \
+                     requires reading all of the data in each file or archive.",
+        ))
+        .arg(
+            Arg::new("magic")
+                .long("magic")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["package", "text_listing"])
+                .help("Detect the type of each file (ELF, PE, gzip, PNG, script, ...) from its contents."),
+        )
+        .arg(
+            Arg::new("compression")
+                .long("compression")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["package", "text_listing"])
+                .help("Show the compressed size and ratio of each file in a zip archive.")
+                .long_help(
+                    "Show the compressed size, uncompressed size, and compression ratio of each \
+                     file in a zip archive, and the totals for each directory. Other formats, \
+                     like 7-zip, compress many files together and don't have per-file sizes.",
+        ))
+        .arg(
+            Arg::new("comments")
+                .long("comments")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["package", "text_listing"])
+                .help("Show the comment of each file in a zip archive after its name.")
+                .long_help(
+                    "Show the comment of each file in a zip archive after its name. The comment \
+                     of the archive itself is always shown below the root.",
+        ))
+        .arg(
+            Arg::new("git")
+                .long("git")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["package", "text_listing"])
+                .help("Show the git status of each file when listing a directory in a work tree.")
+                .long_help(
+                    "Show the git status of each file when listing a directory in a git work \
+                     tree: M for modified, A for added, ? for untracked, ! for ignored, and U for \
+                     conflicted. Directories show the most important status of their contents.",
+        ))
+        .arg(
+            Arg::new("xattrs")
+                .long("xattrs")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["package", "text_listing"])
+                .help("List the extended attributes of each entry below it.")
+                .long_help(
+                    "List the extended attributes of each entry below it. File capabilities \
+                     (security.capability) are decoded into getcap-style text.",
+        ))
+        .arg(
+            Arg::new("acls")
+                .long("acls")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["package", "text_listing"])
+                .help("List the POSIX or NFSv4 access control list of each entry below it."),
+        )
+        .arg(
+            Arg::new("icons")
+                .long("icons")
+                .action(ArgAction::SetTrue)
+                .help("Show a Nerd Font icon before each name.")
+                .long_help(
+                    "Show a Nerd Font icon before each name, chosen by file name, extension, or \
+                     type. Icons can be overridden with $PINE_ICONS, using LS_COLORS-like syntax, \
+                     e.g. 'di=<icon>:*.rs=<icon>:Makefile=<icon>'.",
+        ))
+        .arg(
+            Arg::new("truncate")
+                .long("truncate")
+                .action(ArgAction::SetTrue)
+                .help("Truncate long names so that each entry fits on one line of the terminal."),
+        )
+        .arg(
+            Arg::new("width")
+                .long("width")
+                .value_name("N")
+                .value_parser(value_parser!(usize))
+                .help("Truncate long names so that each entry fits in N columns.")
+                .long_help(
+                    "Truncate long names and link targets with an ellipsis so that each entry \
+                     fits in N columns. Implies --truncate, but overrides the detected terminal \
+                     width.",
+        ))
+        .arg(
+            Arg::new("noreport")
+                .long("noreport")
+                .action(ArgAction::SetTrue)
+                .help("Don't print the count of directories and files at the end of the listing."),
+        )
+        .arg(
+            Arg::new("report_size")
+                .long("report-size")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("noreport")
+                .help("Include the total size of all files in the report."),
+        )
         .arg(
             Arg::new("input")
                 .required(true)
@@ -136,7 +411,62 @@ fn parse_args() -> Args {
     Args {
         color_choice,
         pager: m.get_flag("pager"),
+        report: !m.get_flag("noreport"),
+        report_size: m.get_flag("report_size"),
+        classify: m.get_flag("classify"),
+        full_path: m.get_flag("full_path"),
+        inodes: m.get_flag("inodes"),
+        device: m.get_flag("device"),
+        dir_counts: m.get_flag("dir_counts"),
+        archive_format: m.get_flag("archive_format"),
+        git: m.get_flag("git"),
+        width: m
+            .get_one::<usize>("width")
+            .copied()
+            .or_else(|| m.get_flag("truncate").then(util::terminal_width).flatten()),
+        hyperlink: match m.get_one("hyperlink").map(String::as_str) {
+            Some("always") => true,
+            Some("never") => false,
+            Some("auto") => io::stdout().is_terminal(),
+            _ => unreachable!(),
+        },
+        icons: m.get_flag("icons"),
+        guides: if m.get_flag("indent") {
+            Guides::INDENT
+        } else if m.get_flag("no_indent") {
+            Guides::NO_INDENT
+        } else {
+            m.get_one::<String>("charset")
+                .and_then(|name| Guides::from_name(name))
+                .unwrap_or_else(Guides::from_locale)
+        },
+        quoting: m
+            .get_one::<String>("quoting_style")
+            .and_then(|name| QuotingStyle::from_name(name))
+            .unwrap_or_default(),
+        sort: m
+            .get_one::<String>("sort")
+            .and_then(|name| SortOrder::from_name(name))
+            .unwrap_or_default(),
+        reverse: m.get_flag("reverse"),
+        dir_placement: if m.get_flag("dirsfirst") {
+            DirPlacement::First
+        } else if m.get_flag("filesfirst") {
+            DirPlacement::Last
+        } else {
+            DirPlacement::Mixed
+        },
         input_mode,
+        read_opts: ReadOptions {
+            detect_hardlinks: m.get_flag("hardlinks"),
+            checksum: m.get_one::<String>("checksum").and_then(|s| Checksum::from_name(s)),
+            magic: m.get_flag("magic"),
+            compression: m.get_flag("compression"),
+            comments: m.get_flag("comments"),
+            xattrs: m.get_flag("xattrs"),
+            acls: m.get_flag("acls"),
+            git: m.get_flag("git"),
+        },
         inputs: m.remove_many("input").unwrap().collect(),
     }
 }
@@ -146,7 +476,30 @@ fn run() -> Result<i32> {
     libarchive::fix_posix_locale_for_libarchive();
 
     let args = parse_args();
-    let color = LsColors::from_env().unwrap_or_default();
+    let mut opts = PrintOptions {
+        color: LsColors::from_env().unwrap_or_default(),
+        report: args.report,
+        report_size: args.report_size,
+        classify: args.classify,
+        full_path: args.full_path,
+        inodes: args.inodes,
+        device: args.device,
+        dir_counts: args.dir_counts,
+        archive_format: args.archive_format,
+        git: args.git,
+        width: args.width,
+        icons: args.icons.then(Icons::from_env),
+        guides: args.guides,
+        quoting: args.quoting,
+        sort: args.sort,
+        reverse: args.reverse,
+        dir_placement: args.dir_placement,
+        hyperlink_host: if args.hyperlink {
+            Some(util::hostname().unwrap_or_default())
+        } else {
+            None
+        },
+    };
 
     // evil stdout redirection into a pager process
     let pager_redirect = if args.pager { Some(PagerOutputRedirect::spawn()?) } else { None };
@@ -160,6 +513,7 @@ fn run() -> Result<i32> {
     };
 
     let mut error_count = 0;
+    let mut total = TreeStats::default();
     let mut first = true;
     for input in args.inputs.iter() {
         // print blank lines between entries
@@ -181,14 +535,19 @@ fn run() -> Result<i32> {
                     Err(anyhow!("package name is not valid UTF-8"))
                 }
             }
-            InputMode::Path => PineTree::from_path(input).map_err(Into::into),
+            InputMode::Path => PineTree::from_path(input, &args.read_opts).map_err(Into::into),
             InputMode::TextList(check_fs) => {
                 PineTree::from_text_listing_path(input, check_fs).map_err(Into::into)
             }
         };
 
         match tree_ret {
-            Ok(tree) => tree.print(&mut stdout_lock, &color)?,
+            Ok(tree) => {
+                // only leave room for git markers when there are statuses to show
+                opts.git = args.git && tree.git;
+                tree.print(&mut stdout_lock, &opts)?;
+                total += tree.stats();
+            }
             Err(e) => {
                 let input_name = if input == "-" {
                     std::borrow::Cow::Borrowed("[stdin]")
@@ -201,6 +560,12 @@ fn run() -> Result<i32> {
         }
     }
 
+    // with multiple inputs, finish with a grand total of everything listed
+    if opts.report && args.inputs.len() > 1 {
+        write!(&mut stdout_lock, "\ntotal: ")?;
+        total.write_report(&mut stdout_lock, opts.report_size)?;
+    }
+
     if let Some(mut p) = pager_redirect {
         // flush the output buffer before pager_redirect is dropped and waits for the pager process
         let _ = stdout_lock.flush();
@@ -7,7 +7,7 @@ use std::io::{self, BufRead, BufReader, ErrorKind};
 use std::path::{Path, PathBuf};
 
 use crate::dir_tree::DirTreeError;
-use crate::input::{self, PineTree};
+use crate::input::{self, PineTree, ReadOptions};
 
 pub trait PackageManager {
     /// Find the package with the given name and load its contents into a PineTree. Return Ok(None)
@@ -113,8 +113,18 @@ impl PackageManager for Pacman {
             )
         };
 
-        let tree = input::read_from_archive_file(&path.join("mtree"), path_filter)?;
-        Ok(Some(PineTree { tree, root: Some(real_name.into()) }))
+        let (tree, _) = input::read_from_archive_file(
+            &path.join("mtree"),
+            path_filter,
+            &ReadOptions::default(),
+        )?;
+        Ok(Some(PineTree {
+            tree,
+            root: Some(real_name.into()),
+            notes: Vec::new(),
+            format: None,
+            git: false,
+        }))
     }
 }
 
@@ -268,11 +278,7 @@ impl Dpkg {
                 match key {
                     "Package" => current.name.push_str(val),
                     "Architecture" => current.arch.push_str(val),
-                    "Multi-Arch" => {
-                        if val == "same" {
-                            current.multi_arch_same = true;
-                        }
-                    }
+                    "Multi-Arch" if val == "same" => current.multi_arch_same = true,
                     "Provides" => {
                         for alias in val.split(", ") {
                             // line can look something like
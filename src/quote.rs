@@ -0,0 +1,196 @@
+// Copyright (c) 2021 Allen Wild <allenwild93@gmail.com>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Quoting of names for display, so that control characters and invalid UTF-8 in untrusted names
+//! (e.g. from archives) can't mess with the terminal and are shown unambiguously.
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+/// How names are quoted, similar to the `--quoting-style` option of `ls`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuotingStyle {
+    /// print names as-is, with invalid UTF-8 replaced
+    Literal,
+    /// backslash escapes for control characters, invalid UTF-8, and backslashes
+    #[default]
+    Escape,
+    /// like Escape, but also escape double quotes and surround the name with them
+    C,
+    /// quote names for a POSIX shell, using `$'...'` for control characters and invalid UTF-8
+    Shell,
+}
+
+/// Characters that don't need quoting in a shell word
+fn is_shell_safe(c: char) -> bool {
+    c.is_alphanumeric() || "-_./:,+@%=".contains(c)
+}
+
+/// Append the C escape sequence for a byte that isn't printed as-is.
+fn push_escaped_byte(out: &mut String, byte: u8) {
+    match byte {
+        0x07 => out.push_str("\\a"),
+        0x08 => out.push_str("\\b"),
+        b'\t' => out.push_str("\\t"),
+        b'\n' => out.push_str("\\n"),
+        0x0b => out.push_str("\\v"),
+        0x0c => out.push_str("\\f"),
+        b'\r' => out.push_str("\\r"),
+        _ => out.push_str(&format!("\\{:03o}", byte)),
+    }
+}
+
+/// Split a name into pieces of printable text and raw bytes that need escaping, i.e. control
+/// characters and invalid UTF-8.
+fn pieces(bytes: &[u8]) -> impl Iterator<Item = Result<char, u8>> + '_ {
+    bytes.utf8_chunks().flat_map(|chunk| {
+        let valid = chunk.valid();
+        let chars = valid.char_indices().flat_map(move |(i, c)| {
+            let raw = &valid.as_bytes()[i..i + c.len_utf8()];
+            let escaped = c.is_control().then(|| raw.iter().copied().map(Err));
+            let plain = (!c.is_control()).then_some(Ok(c));
+            escaped.into_iter().flatten().chain(plain)
+        });
+        chars.chain(chunk.invalid().iter().copied().map(Err))
+    })
+}
+
+impl QuotingStyle {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "literal" => Some(Self::Literal),
+            "escape" => Some(Self::Escape),
+            "c" => Some(Self::C),
+            "shell" => Some(Self::Shell),
+            _ => None,
+        }
+    }
+
+    /// Quote a name for display in this style. Names that don't need any changes are borrowed.
+    pub fn quote(self, name: &OsStr) -> Cow<'_, str> {
+        let bytes = name.as_bytes();
+        let plain = match std::str::from_utf8(bytes) {
+            Ok(s) => Some(s),
+            Err(_) if self == Self::Literal => return String::from_utf8_lossy(bytes),
+            Err(_) => None,
+        };
+
+        match self {
+            Self::Literal => Cow::Borrowed(plain.unwrap_or_default()),
+            Self::Escape | Self::C => {
+                let needs_escape = |c: char| c.is_control() || c == '\\';
+                if let (Self::Escape, Some(s)) = (self, plain) {
+                    if !s.contains(needs_escape) {
+                        return Cow::Borrowed(s);
+                    }
+                }
+                let mut out = String::with_capacity(bytes.len() + 2);
+                if self == Self::C {
+                    out.push('"');
+                }
+                for piece in pieces(bytes) {
+                    match piece {
+                        Ok('\\') => out.push_str("\\\\"),
+                        Ok('"') if self == Self::C => out.push_str("\\\""),
+                        Ok(c) => out.push(c),
+                        Err(byte) => push_escaped_byte(&mut out, byte),
+                    }
+                }
+                if self == Self::C {
+                    out.push('"');
+                }
+                Cow::Owned(out)
+            }
+            Self::Shell => {
+                if let Some(s) = plain.filter(|s| !s.is_empty() && s.chars().all(is_shell_safe)) {
+                    return Cow::Borrowed(s);
+                }
+                // Printable text goes in single quotes, and everything else in $'...' which
+                // understands C escapes, e.g. 'a'$'\n''b' for "a\nb".
+                let mut out = String::from("'");
+                let mut in_dollar = false;
+                for piece in pieces(bytes) {
+                    match piece {
+                        Ok(c) => {
+                            if in_dollar {
+                                out.push_str("''");
+                                in_dollar = false;
+                            }
+                            match c {
+                                '\'' => out.push_str("'\\''"),
+                                _ => out.push(c),
+                            }
+                        }
+                        Err(byte) => {
+                            if !in_dollar {
+                                out.push_str("'$'");
+                                in_dollar = true;
+                            }
+                            push_escaped_byte(&mut out, byte);
+                        }
+                    }
+                }
+                out.push('\'');
+                Cow::Owned(out)
+            }
+        }
+    }
+
+    /// Escape free-form text like comments and notes for display. Unlike names, text isn't quoted
+    /// and only control characters are escaped, so it stays readable. The literal style leaves
+    /// the text as-is.
+    pub fn escape_text(self, text: &str) -> Cow<'_, str> {
+        if self == Self::Literal || !text.contains(char::is_control) {
+            return Cow::Borrowed(text);
+        }
+        let mut out = String::with_capacity(text.len());
+        for piece in pieces(text.as_bytes()) {
+            match piece {
+                Ok(c) => out.push(c),
+                Err(byte) => push_escaped_byte(&mut out, byte),
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    use super::QuotingStyle;
+
+    #[test]
+    fn test_quote() {
+        fn quote(style: QuotingStyle, name: &[u8]) -> Cow<'_, str> {
+            style.quote(OsStr::from_bytes(name))
+        }
+        let hostile = b"a b\x1b[31m\\\xff\"\n";
+
+        assert_eq!(quote(QuotingStyle::Literal, b"a\nb"), "a\nb");
+        assert_eq!(quote(QuotingStyle::Literal, b"a\xffb"), "a\u{fffd}b");
+        assert_eq!(quote(QuotingStyle::Escape, b"plain name.txt"), "plain name.txt");
+        assert_eq!(quote(QuotingStyle::Escape, hostile), "a b\\033[31m\\\\\\377\"\\n");
+        assert_eq!(quote(QuotingStyle::Escape, "\u{85}é".as_bytes()), "\\302\\205é");
+        assert_eq!(quote(QuotingStyle::C, b"plain"), "\"plain\"");
+        assert_eq!(quote(QuotingStyle::C, hostile), "\"a b\\033[31m\\\\\\377\\\"\\n\"");
+        assert_eq!(quote(QuotingStyle::Shell, b"plain-name.txt"), "plain-name.txt");
+        assert_eq!(quote(QuotingStyle::Shell, b""), "''");
+        assert_eq!(quote(QuotingStyle::Shell, b"it's"), "'it'\\''s'");
+        assert_eq!(quote(QuotingStyle::Shell, b"a\n\tb"), "'a'$'\\n\\t''b'");
+        assert_eq!(quote(QuotingStyle::Shell, b"\xff"), "''$'\\377'");
+    }
+
+    #[test]
+    fn test_escape_text() {
+        let text = "Don't \"quote\" C:\\\x1b[31m\n";
+        assert_eq!(QuotingStyle::Literal.escape_text(text), text);
+        for style in [QuotingStyle::Escape, QuotingStyle::C, QuotingStyle::Shell] {
+            assert_eq!(style.escape_text("Don't \"quote\""), "Don't \"quote\"");
+            assert_eq!(style.escape_text(text), "Don't \"quote\" C:\\\\033[31m\\n");
+        }
+    }
+}
@@ -1,8 +1,14 @@
 // Copyright (c) 2021 Allen Wild <allenwild93@gmail.com>
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::env;
+use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
 
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
 /// Path::new("foo").parent() == Some("") which is weird and not really what I want.
 /// This does the same thing but also returns None if the parent is empty
 pub fn dirname(path: &Path) -> Option<&Path> {
@@ -12,6 +18,111 @@ pub fn dirname(path: &Path) -> Option<&Path> {
     }
 }
 
+/// Format a size in bytes as a human-readable string with binary unit prefixes, e.g. "1.5 MiB".
+/// Sizes less than 1 KiB are printed as an exact number of bytes.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Compare two names like `sort -V`, treating runs of digits as numbers so that `lib-1.9` sorts
+/// before `lib-1.10`. Numbers that are equal but written differently, like `01` and `1`, are
+/// ordered byte-wise as a tiebreaker.
+pub fn version_cmp(a: &Path, b: &Path) -> Ordering {
+    /// Split off the leading run of digits, or of non-digits
+    fn split_run(s: &[u8], digits: bool) -> (&[u8], &[u8]) {
+        let len = s.iter().position(|b| b.is_ascii_digit() != digits).unwrap_or(s.len());
+        s.split_at(len)
+    }
+
+    let (mut x, mut y) = (a.as_os_str().as_bytes(), b.as_os_str().as_bytes());
+    while !x.is_empty() || !y.is_empty() {
+        let (xtext, xrest) = split_run(x, false);
+        let (ytext, yrest) = split_run(y, false);
+        let (xnum, xrest) = split_run(xrest, true);
+        let (ynum, yrest) = split_run(yrest, true);
+        let trim = |num: &'_ [u8]| -> usize { num.iter().take_while(|&&b| b == b'0').count() };
+        let (xnum, ynum) = (&xnum[trim(xnum)..], &ynum[trim(ynum)..]);
+        let ord =
+            xtext.cmp(ytext).then_with(|| xnum.len().cmp(&ynum.len())).then_with(|| xnum.cmp(ynum));
+        if ord != Ordering::Equal {
+            return ord;
+        }
+        (x, y) = (xrest, yrest);
+    }
+    a.cmp(b)
+}
+
+/// Get the width of the terminal in columns, from stdout or stderr if either is a terminal, or
+/// otherwise from $COLUMNS.
+pub fn terminal_width() -> Option<usize> {
+    for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        // SAFETY: TIOCGWINSZ writes a struct winsize to its pointer argument
+        if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+            return Some(size.ws_col.into());
+        }
+    }
+    env::var("COLUMNS").ok()?.parse().ok().filter(|&cols| cols > 0)
+}
+
+/// Shorten `s` to at most `max_width` columns of display width, replacing the end with an
+/// ellipsis if anything was cut off.
+pub fn truncate_to_width(s: &str, max_width: usize) -> Cow<'_, str> {
+    if s.width() <= max_width {
+        return Cow::Borrowed(s);
+    }
+    let mut width = 0;
+    let mut out = String::new();
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        // leave room for the ellipsis
+        if width + w + 1 > max_width {
+            break;
+        }
+        width += w;
+        out.push(c);
+    }
+    if max_width > 0 {
+        out.push('…');
+    }
+    Cow::Owned(out)
+}
+
+/// Get the system's hostname, or None if it can't be determined.
+pub fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: gethostname writes at most buf.len() bytes
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+/// Build a `file://` URL for an absolute path, percent-encoding everything other than unreserved
+/// characters and slashes, the same as `ls --hyperlink`.
+pub fn file_url(host: &str, path: &Path) -> String {
+    let mut url = format!("file://{}", host);
+    for &b in path.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            url.push(b as char);
+        } else {
+            url.push_str(&format!("%{:02X}", b));
+        }
+    }
+    url
+}
+
 // Printing things with ansi_term involves some weird Cow trait bounds that break things, so I want
 // to use termcolor instead. lscolors::Style has a method to convert to an ansi_term::Style, but
 // not to a termcolor::ColorSpec, so roll my own conversion with some extension traits.
@@ -56,3 +167,21 @@ impl ToColorSpec for lscolors::Style {
         cs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::file_url;
+
+    #[test]
+    fn test_file_url() {
+        assert_eq!(file_url("host", Path::new("/a/b-c_d.e~")), "file://host/a/b-c_d.e~");
+        assert_eq!(file_url("", Path::new("/my files/100%.txt")), "file:///my%20files/100%25.txt");
+        assert_eq!(file_url("h", Path::new("/café/?#")), "file://h/caf%C3%A9/%3F%23");
+        let raw = Path::new(OsStr::from_bytes(b"/bad\xff"));
+        assert_eq!(file_url("h", raw), "file://h/bad%FF");
+    }
+}
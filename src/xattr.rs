@@ -0,0 +1,309 @@
+// Copyright (c) 2021 Allen Wild <allenwild93@gmail.com>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Extended attributes, including decoding of the binary POSIX ACL and file capability
+//! attributes that Linux stores as xattrs.
+
+use std::ffi::CString;
+use std::fmt;
+use std::io;
+use std::os::raw::c_char;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::quote::QuotingStyle;
+
+const POSIX_ACL_ACCESS: &str = "system.posix_acl_access";
+const POSIX_ACL_DEFAULT: &str = "system.posix_acl_default";
+const CAPABILITY: &str = "security.capability";
+
+/// Values longer than this are truncated when printed as hex.
+const MAX_HEX_LEN: usize = 32;
+
+/// Names of Linux capabilities, indexed by their bit number.
+const CAP_NAMES: [&str; 41] = [
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// A single extended attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xattr {
+    pub name: String,
+    pub value: Vec<u8>,
+}
+
+impl Xattr {
+    pub fn new(name: impl Into<String>, value: impl Into<Vec<u8>>) -> Self {
+        Self { name: name.into(), value: value.into() }
+    }
+
+    /// If this is one of the POSIX ACL attributes, decode it into getfacl-style text lines, e.g.
+    /// `user:1000:r--`. Entries of the default ACL are prefixed with `default:`. Returns None for
+    /// other attributes or malformed values.
+    pub fn posix_acl(&self) -> Option<Vec<String>> {
+        let prefix = match self.name.as_str() {
+            POSIX_ACL_ACCESS => "",
+            POSIX_ACL_DEFAULT => "default:",
+            _ => return None,
+        };
+
+        // 4-byte version header, followed by 8-byte entries of (u16 tag, u16 perm, u32 id)
+        let (header, entries) = self.value.split_at_checked(4)?;
+        if u32::from_le_bytes(header.try_into().unwrap()) != 2 || entries.len() % 8 != 0 {
+            return None;
+        }
+        let lines = entries.chunks_exact(8).map(|entry| {
+            let tag = u16::from_le_bytes([entry[0], entry[1]]);
+            let perm = u16::from_le_bytes([entry[2], entry[3]]);
+            let id = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+            let (kind, qualifier) = match tag {
+                0x01 => ("user", String::new()),
+                0x02 => ("user", id.to_string()),
+                0x04 => ("group", String::new()),
+                0x08 => ("group", id.to_string()),
+                0x10 => ("mask", String::new()),
+                0x20 => ("other", String::new()),
+                _ => ("unknown", id.to_string()),
+            };
+            let rwx: String = [(4, 'r'), (2, 'w'), (1, 'x')]
+                .iter()
+                .map(|&(bit, c)| if perm & bit != 0 { c } else { '-' })
+                .collect();
+            format!("{}{}:{}:{}", prefix, kind, qualifier, rwx)
+        });
+        Some(lines.collect())
+    }
+
+    /// Display this attribute as `name: value`, with the name escaped in the given quoting style.
+    pub fn display(&self, quoting: QuotingStyle) -> XattrDisplay<'_> {
+        XattrDisplay { xattr: self, quoting }
+    }
+}
+
+/// Helper for printing an [`Xattr`], returned by [`Xattr::display`].
+pub struct XattrDisplay<'a> {
+    xattr: &'a Xattr,
+    quoting: QuotingStyle,
+}
+
+impl fmt::Display for XattrDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Xattr { name, value } = self.xattr;
+        // names come from untrusted archives too, so don't let them send escape sequences
+        write!(f, "{}: ", self.quoting.escape_text(name))?;
+        if name == CAPABILITY {
+            if let Some(caps) = decode_capability(value) {
+                return write!(f, "{}", caps);
+            }
+        }
+
+        // strings are often stored with a trailing NUL
+        let text = value.strip_suffix(b"\0").unwrap_or(value);
+        match std::str::from_utf8(text) {
+            Ok(s) if !s.chars().any(char::is_control) => write!(f, "{:?}", s),
+            _ => {
+                write!(f, "0x")?;
+                for byte in value.iter().take(MAX_HEX_LEN) {
+                    write!(f, "{:02x}", byte)?;
+                }
+                if value.len() > MAX_HEX_LEN {
+                    write!(f, "... ({} bytes)", value.len())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Decode a `security.capability` value (`struct vfs_cap_data`) into getcap-style text, e.g.
+/// `cap_net_admin,cap_net_raw=ep`. Returns None if the value is malformed.
+fn decode_capability(value: &[u8]) -> Option<String> {
+    const VFS_CAP_REVISION_MASK: u32 = 0xff00_0000;
+    const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x1;
+
+    let words: Vec<u32> =
+        value.chunks_exact(4).map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap())).collect();
+    let magic = *words.first()?;
+    let effective = magic & VFS_CAP_FLAGS_EFFECTIVE != 0;
+    // revision 1 has one 32-bit (permitted, inheritable) pair, revisions 2 and 3 have two, and
+    // revision 3 adds the root uid of the user namespace.
+    let (permitted, inheritable, rootid) = match (magic & VFS_CAP_REVISION_MASK, words.len()) {
+        (0x0100_0000, 3) => (u64::from(words[1]), u64::from(words[2]), None),
+        (0x0200_0000, 5) | (0x0300_0000, 6) => (
+            u64::from(words[1]) | (u64::from(words[3]) << 32),
+            u64::from(words[2]) | (u64::from(words[4]) << 32),
+            words.get(5).copied(),
+        ),
+        _ => return None,
+    };
+
+    // group capabilities that have the same set of flags, like getcap does
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+    for bit in 0..64 {
+        let mask = 1u64 << bit;
+        let flags: String = [
+            (effective && (permitted | inheritable) & mask != 0, 'e'),
+            (inheritable & mask != 0, 'i'),
+            (permitted & mask != 0, 'p'),
+        ]
+        .iter()
+        .filter_map(|&(set, c)| set.then_some(c))
+        .collect();
+        if flags.is_empty() {
+            continue;
+        }
+        let name = match CAP_NAMES.get(bit) {
+            Some(name) => format!("cap_{}", name),
+            None => bit.to_string(),
+        };
+        match groups.iter_mut().find(|(f, _)| *f == flags) {
+            Some((_, names)) => names.push(name),
+            None => groups.push((flags, vec![name])),
+        }
+    }
+
+    let mut text = groups
+        .iter()
+        .map(|(flags, names)| format!("{}={}", names.join(","), flags))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.is_empty() {
+        text.push('=');
+    }
+    if let Some(rootid) = rootid {
+        text.push_str(&format!(" [rootid={}]", rootid));
+    }
+    Some(text)
+}
+
+/// Call a listxattr/getxattr style function, first to get the needed size and then again to fill
+/// a buffer. Retries if the value grew in between the two calls.
+fn read_xattr_buf(f: impl Fn(*mut c_char, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let size = f(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        let ret = f(buf.as_mut_ptr().cast(), buf.len());
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+            return Err(err);
+        }
+        buf.truncate(ret as usize);
+        return Ok(buf);
+    }
+}
+
+/// Read all extended attributes of a file on disk, without following symlinks. Filesystems
+/// that don't support xattrs give an empty list.
+pub fn read_xattrs(path: &Path) -> io::Result<Vec<Xattr>> {
+    let cpath = CString::new(path.as_os_str().as_bytes())?;
+    let names =
+        match read_xattr_buf(|buf, size| unsafe { libc::llistxattr(cpath.as_ptr(), buf, size) }) {
+            Ok(names) => names,
+            Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+    let mut xattrs = Vec::new();
+    // names are a list of NUL-terminated strings
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let cname = CString::new(name)?;
+        let value = match read_xattr_buf(|buf, size| unsafe {
+            libc::lgetxattr(cpath.as_ptr(), cname.as_ptr(), buf.cast(), size)
+        }) {
+            Ok(value) => value,
+            // removed since we listed it
+            Err(err) if err.raw_os_error() == Some(libc::ENODATA) => continue,
+            Err(err) => return Err(err),
+        };
+        xattrs.push(Xattr::new(String::from_utf8_lossy(name), value));
+    }
+    Ok(xattrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Xattr;
+    use crate::quote::QuotingStyle;
+
+    #[test]
+    fn test_display() {
+        // cap_net_raw=ep, VFS_CAP_REVISION_2
+        let mut cap = Vec::new();
+        for word in [0x0200_0001u32, 1 << 13, 0, 0, 0] {
+            cap.extend_from_slice(&word.to_le_bytes());
+        }
+        fn display(name: &str, value: impl Into<Vec<u8>>, quoting: QuotingStyle) -> String {
+            Xattr::new(name, value).display(quoting).to_string()
+        }
+        let escape = QuotingStyle::Escape;
+        assert_eq!(
+            display("security.capability", cap, escape),
+            "security.capability: cap_net_raw=ep"
+        );
+        assert_eq!(display("user.mime", "text/plain", escape), "user.mime: \"text/plain\"");
+        assert_eq!(display("user.bin", [0u8, 0xff], escape), "user.bin: 0x00ff");
+        assert_eq!(display("user.\x1b[32m\"x\"", "", escape), "user.\\033[32m\"x\": \"\"");
+        assert_eq!(display("user.\x1b", "", QuotingStyle::Literal), "user.\x1b: \"\"");
+    }
+
+    #[test]
+    fn test_posix_acl() {
+        let mut acl = 2u32.to_le_bytes().to_vec();
+        for (tag, perm, id) in [(0x01u16, 6u16, u32::MAX), (0x02, 4, 1000), (0x20, 0, u32::MAX)] {
+            acl.extend_from_slice(&tag.to_le_bytes());
+            acl.extend_from_slice(&perm.to_le_bytes());
+            acl.extend_from_slice(&id.to_le_bytes());
+        }
+        assert_eq!(
+            Xattr::new("system.posix_acl_default", acl).posix_acl().unwrap(),
+            vec!["default:user::rw-", "default:user:1000:r--", "default:other::---"]
+        );
+        assert_eq!(Xattr::new("user.foo", "bar").posix_acl(), None);
+    }
+}
@@ -0,0 +1,386 @@
+// Copyright (c) 2021 Allen Wild <allenwild93@gmail.com>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Minimal reader for the central directory of zip files, for metadata that libarchive doesn't
+//! expose, like the compressed size of each entry and comments.
+
+use std::ffi::OsStr;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+const EOCD_SIG: &[u8; 4] = b"PK\x05\x06";
+const EOCD_LEN: u64 = 22;
+const ZIP64_LOCATOR_SIG: &[u8; 4] = b"PK\x06\x07";
+const ZIP64_LOCATOR_LEN: u64 = 20;
+const ZIP64_EOCD_SIG: &[u8; 4] = b"PK\x06\x06";
+const CENTRAL_HEADER_SIG: &[u8; 4] = b"PK\x01\x02";
+const CENTRAL_HEADER_LEN: usize = 46;
+/// General purpose flag bit for names and comments encoded as UTF-8
+const FLAG_UTF8: u16 = 1 << 11;
+/// ID of the extra field that holds 64-bit sizes and offsets
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+/// Information about one entry from the zip central directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    /// path of the entry, as stored in the archive
+    pub path: PathBuf,
+    pub compressed_size: u64,
+    pub size: u64,
+    /// the entry's comment, empty if it doesn't have one
+    pub comment: String,
+}
+
+/// The contents of a zip file's central directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipDirectory {
+    pub entries: Vec<ZipEntry>,
+    /// the archive comment, empty if it doesn't have one
+    pub comment: String,
+}
+
+/// Decode a name or comment. UTF-8 is used if the flag says so, and the legacy encoding is
+/// officially CP437, but in practice most tools write whatever the local encoding is, which is
+/// usually UTF-8 too.
+fn decode_text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Little-endian field reader over a byte slice. Reading past the end is an InvalidData error.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid("truncated zip structure"));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Location of the central directory, from the end of central directory record.
+struct CentralDirectory {
+    /// length of the whole file
+    file_len: u64,
+    offset: u64,
+    size: u64,
+    entries: u64,
+    comment: String,
+}
+
+/// Find the end of central directory record by searching backwards from the end of the file,
+/// since it's followed by a variable-length comment. Returns None if the file isn't a zip.
+fn find_central_directory<R: Read + Seek>(r: &mut R) -> io::Result<Option<CentralDirectory>> {
+    let file_len = r.seek(SeekFrom::End(0))?;
+    let tail_len = file_len.min(EOCD_LEN + u64::from(u16::MAX));
+    let tail_start = file_len - tail_len;
+    r.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = Vec::with_capacity(tail_len as usize);
+    r.by_ref().take(tail_len).read_to_end(&mut tail)?;
+
+    if tail.len() < EOCD_LEN as usize {
+        return Ok(None);
+    }
+    // the comment could contain the signature too, so also check that the comment length
+    // matches the rest of the file
+    let eocd_pos = match (0..=tail.len() - EOCD_LEN as usize).rev().find(|&i| {
+        tail[i..].starts_with(EOCD_SIG) && {
+            let comment_len = usize::from(u16::from_le_bytes([tail[i + 20], tail[i + 21]]));
+            i + EOCD_LEN as usize + comment_len == tail.len()
+        }
+    }) {
+        Some(pos) => pos,
+        None => return Ok(None),
+    };
+
+    let mut f = Fields(&tail[eocd_pos + 4..]);
+    let _disk = f.u16()?;
+    let _cd_disk = f.u16()?;
+    let _disk_entries = f.u16()?;
+    let entries = f.u16()?;
+    let size = f.u32()?;
+    let offset = f.u32()?;
+    let comment_len = f.u16()?;
+    let mut cd = CentralDirectory {
+        file_len,
+        offset: u64::from(offset),
+        size: u64::from(size),
+        entries: u64::from(entries),
+        comment: decode_text(f.bytes(comment_len.into())?),
+    };
+
+    // values that don't fit are set to all ones, and the real values are in the zip64 record
+    if entries == u16::MAX || size == u32::MAX || offset == u32::MAX {
+        let locator_pos = (tail_start + eocd_pos as u64)
+            .checked_sub(ZIP64_LOCATOR_LEN)
+            .ok_or_else(|| invalid("missing zip64 end of central directory locator"))?;
+        r.seek(SeekFrom::Start(locator_pos))?;
+        let mut locator = [0u8; ZIP64_LOCATOR_LEN as usize];
+        r.read_exact(&mut locator)?;
+        let mut f = Fields(&locator);
+        if f.bytes(4)? != ZIP64_LOCATOR_SIG {
+            return Err(invalid("bad zip64 end of central directory locator"));
+        }
+        let _disk = f.u32()?;
+        let eocd64_pos = f.u64()?;
+
+        r.seek(SeekFrom::Start(eocd64_pos))?;
+        let mut eocd64 = [0u8; 56];
+        r.read_exact(&mut eocd64)?;
+        let mut f = Fields(&eocd64);
+        if f.bytes(4)? != ZIP64_EOCD_SIG {
+            return Err(invalid("bad zip64 end of central directory record"));
+        }
+        // skip record size, versions, and disk numbers
+        f.bytes(8 + 2 + 2 + 4 + 4 + 8)?;
+        cd.entries = f.u64()?;
+        cd.size = f.u64()?;
+        cd.offset = f.u64()?;
+    }
+
+    Ok(Some(cd))
+}
+
+/// Read the list of entries and the archive comment from a zip file's central directory. Returns
+/// `Ok(None)` if the file doesn't look like a zip file at all.
+pub fn read_directory<R: Read + Seek>(r: &mut R) -> io::Result<Option<ZipDirectory>> {
+    let cd = match find_central_directory(r)? {
+        Some(cd) => cd,
+        None => return Ok(None),
+    };
+
+    // The sizes come from the file, so check them before trusting them, and don't reserve
+    // space based on them. A bogus entry count makes the loop below run out of data.
+    if cd.offset.checked_add(cd.size).is_none_or(|end| end > cd.file_len) {
+        return Err(invalid("zip central directory is past the end of the file"));
+    }
+    r.seek(SeekFrom::Start(cd.offset))?;
+    let mut data = Vec::new();
+    r.by_ref().take(cd.size).read_to_end(&mut data)?;
+
+    let mut entries = Vec::new();
+    let mut f = Fields(&data);
+    for _ in 0..cd.entries {
+        let mut header = Fields(f.bytes(CENTRAL_HEADER_LEN)?);
+        if header.bytes(4)? != CENTRAL_HEADER_SIG {
+            return Err(invalid("bad zip central directory header"));
+        }
+        // skip versions
+        header.bytes(2 + 2)?;
+        let flags = header.u16()?;
+        // skip compression method, time, date, and CRC
+        header.bytes(2 + 2 + 2 + 4)?;
+        let mut compressed_size = u64::from(header.u32()?);
+        let mut size = u64::from(header.u32()?);
+        let name_len = header.u16()? as usize;
+        let extra_len = header.u16()? as usize;
+        let comment_len = header.u16()? as usize;
+
+        let name = f.bytes(name_len)?;
+        let mut extra = Fields(f.bytes(extra_len)?);
+        let comment = f.bytes(comment_len)?;
+
+        // The zip64 extra field contains only the values that were too big for the header, in
+        // a fixed order.
+        while let (Ok(id), Ok(len)) = (extra.u16(), extra.u16()) {
+            let mut field = Fields(extra.bytes(len as usize)?);
+            if id == ZIP64_EXTRA_ID {
+                if size == u64::from(u32::MAX) {
+                    size = field.u64()?;
+                }
+                if compressed_size == u64::from(u32::MAX) {
+                    compressed_size = field.u64()?;
+                }
+            }
+        }
+
+        // Non-UTF-8 names are passed through as raw bytes, which is what libarchive does too
+        // when it can't convert them.
+        let path = if flags & FLAG_UTF8 != 0 {
+            PathBuf::from(decode_text(name))
+        } else {
+            PathBuf::from(OsStr::from_bytes(name))
+        };
+        entries.push(ZipEntry { path, compressed_size, size, comment: decode_text(comment) });
+    }
+
+    Ok(Some(ZipDirectory { entries, comment: cd.comment }))
+}
+
+/// Helpers for building small zip files in tests.
+#[cfg(test)]
+pub(crate) mod testing {
+    /// One file for [`build_zip`]. The data is always stored as-is, even if `size` claims
+    /// otherwise, and encrypted entries only have the flag set.
+    #[derive(Debug, Default, Clone, Copy)]
+    pub(crate) struct TestEntry<'a> {
+        pub name: &'a str,
+        pub data: &'a [u8],
+        /// uncompressed size to record instead of `data.len()`, which marks the entry deflated
+        pub size: Option<u32>,
+        pub encrypted: bool,
+        pub comment: &'a str,
+    }
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in data {
+            crc ^= u32::from(byte);
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    /// Build a zip file with local headers, data, and a central directory for `entries`, and
+    /// the given archive comment.
+    pub(crate) fn build_zip(entries: &[TestEntry], comment: &[u8]) -> Vec<u8> {
+        let mut zip = Vec::new();
+        let mut central = Vec::new();
+        for entry in entries {
+            let offset = zip.len() as u32;
+            let method: u16 = if entry.size.is_some() { 8 } else { 0 };
+            let size = entry.size.unwrap_or(entry.data.len() as u32);
+            // version needed, flags, method, time, date, CRC, compressed size, size, name length
+            let mut fields = 20u16.to_le_bytes().to_vec();
+            fields.extend_from_slice(&u16::from(entry.encrypted).to_le_bytes());
+            fields.extend_from_slice(&method.to_le_bytes());
+            fields.extend_from_slice(&[0u8; 4]);
+            fields.extend_from_slice(&crc32(entry.data).to_le_bytes());
+            fields.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&size.to_le_bytes());
+            fields.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+
+            zip.extend_from_slice(b"PK\x03\x04");
+            zip.extend_from_slice(&fields);
+            zip.extend_from_slice(&[0u8; 2]);
+            zip.extend_from_slice(entry.name.as_bytes());
+            zip.extend_from_slice(entry.data);
+
+            // the central header adds version made by, and after the local fields, extra and
+            // comment lengths, disk number, attributes, and the local header offset
+            central.extend_from_slice(b"PK\x01\x02");
+            central.extend_from_slice(&20u16.to_le_bytes());
+            central.extend_from_slice(&fields);
+            central.extend_from_slice(&[0u8; 2]);
+            central.extend_from_slice(&(entry.comment.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0u8; 8]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(entry.name.as_bytes());
+            central.extend_from_slice(entry.comment.as_bytes());
+        }
+        let cd_offset = zip.len() as u32;
+        zip.extend_from_slice(&central);
+        zip.extend_from_slice(b"PK\x05\x06");
+        zip.extend_from_slice(&[0u8; 4]);
+        zip.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&cd_offset.to_le_bytes());
+        zip.extend_from_slice(&(comment.len() as u16).to_le_bytes());
+        zip.extend_from_slice(comment);
+        zip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::testing::{build_zip, TestEntry};
+    use super::{read_directory, ZipDirectory, ZipEntry};
+
+    #[test]
+    fn test_read_directory() {
+        // the archive comment includes a fake end of central directory record
+        let comment = b"PK\x05\x06 looks like a record\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+        let entries = [
+            TestEntry { name: "dir/", ..Default::default() },
+            TestEntry {
+                name: "dir/file.txt",
+                data: &[0; 100],
+                size: Some(400),
+                comment: "notes",
+                ..Default::default()
+            },
+        ];
+        let zip = build_zip(&entries, comment);
+        let dir = read_directory(&mut Cursor::new(zip)).unwrap().unwrap();
+        assert_eq!(
+            dir,
+            ZipDirectory {
+                entries: vec![
+                    ZipEntry {
+                        path: "dir/".into(),
+                        compressed_size: 0,
+                        size: 0,
+                        comment: String::new()
+                    },
+                    ZipEntry {
+                        path: "dir/file.txt".into(),
+                        compressed_size: 100,
+                        size: 400,
+                        comment: "notes".into()
+                    },
+                ],
+                comment: String::from_utf8(comment.to_vec()).unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_not_a_zip() {
+        let data = b"this is not a zip file".to_vec();
+        assert!(read_directory(&mut Cursor::new(data)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_truncated() {
+        // shorter than an end of central directory record, but starts like one
+        for data in [&b"PK\x05\x06"[..], b"PK\x05\x06\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0", b""] {
+            assert!(read_directory(&mut Cursor::new(data)).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_bogus_zip64_sizes() {
+        // a zip64 end record claiming a huge central directory, with no actual entries
+        fn build_zip64(entries: u64, size: u64) -> Vec<u8> {
+            let mut zip = b"PK\x06\x06".to_vec();
+            zip.extend_from_slice(&[0u8; 28]);
+            zip.extend_from_slice(&entries.to_le_bytes());
+            zip.extend_from_slice(&size.to_le_bytes());
+            zip.extend_from_slice(&0u64.to_le_bytes());
+            zip.extend_from_slice(b"PK\x06\x07");
+            zip.extend_from_slice(&[0u8; 16]);
+            zip.extend_from_slice(b"PK\x05\x06");
+            zip.extend_from_slice(&[0u8; 4]);
+            zip.extend_from_slice(&[0xff; 12]);
+            zip.extend_from_slice(&[0u8; 2]);
+            zip
+        }
+        for (entries, size) in [(1 << 62, 1 << 62), (1 << 62, 0), (0, u64::MAX)] {
+            assert!(read_directory(&mut Cursor::new(build_zip64(entries, size))).is_err());
+        }
+    }
+}